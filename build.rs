@@ -0,0 +1,57 @@
+//! 主机端工具：把 `assets/samples/*.wav` 转换成固件里的 PCM 采样。
+//!
+//! 每个 WAV 文件（PCM 编码，8/16 位，单声道或立体声，任意采样率）会被混为单声道、
+//! 线性插值重采样到 `SAMPLE_RATE`，再量化成 8 位无符号 PCM 写入 `OUT_DIR/<name>.pcm`。
+//! 生成的 `OUT_DIR/samples.rs` 为每个文件定义一个 `Sample` 常量，
+//! 常量名为文件名的大写形式，例如 `hit.wav` -> `HIT`。
+//! 转换本身在 `musicbox_core::wav` 里，有主机端测试。
+
+use musicbox_core::wav;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::{env, fs};
+
+/// 必须与 `src/sample.rs` 中的 `SAMPLE_RATE` 一致
+const SAMPLE_RATE: u32 = 8000;
+const SAMPLE_DIR: &str = "assets/samples";
+
+fn main() {
+    println!("cargo:rerun-if-changed={SAMPLE_DIR}");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let mut samples_rs = String::new();
+
+    let mut paths: Vec<_> = fs::read_dir(SAMPLE_DIR)
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+
+    for path in paths {
+        if path.extension().and_then(|ext| ext.to_str()) != Some("wav") {
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let wav = fs::read(&path).unwrap();
+        let pcm = wav::convert(&wav, SAMPLE_RATE)
+            .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        fs::write(Path::new(&out_dir).join(format!("{name}.pcm")), &pcm).unwrap();
+
+        let ident = name.to_uppercase().replace(['-', ' ', '.'], "_");
+        writeln!(
+            samples_rs,
+            "pub const {ident}: Sample = Sample {{\n    \
+                name: {name:?},\n    \
+                data: include_bytes!(concat!(env!(\"OUT_DIR\"), \"/{name}.pcm\")),\n\
+            }};"
+        )
+        .unwrap();
+    }
+
+    fs::write(Path::new(&out_dir).join("samples.rs"), samples_rs).unwrap();
+}
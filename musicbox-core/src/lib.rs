@@ -0,0 +1,13 @@
+//! 音乐盒中与硬件无关的部分，固件和主机端测试共用
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod pitch;
+pub mod rhythm;
+pub mod theremin;
+pub mod volume;
+pub mod wavetable;
+#[cfg(any(test, feature = "std"))]
+pub mod wav;
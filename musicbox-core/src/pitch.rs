@@ -0,0 +1,54 @@
+/// 频率的定点精度：1/16 Hz，让颤音等细小的音高变化不被整数 Hz 量化
+pub const FREQ_SCALE: u32 = 16;
+
+/// 2^(k/12) 的 Q16 定点值，k = 0..=12
+const SEMITONE_RATIO: [u32; 13] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715, 131072,
+];
+
+/// 把频率移动 `cents` 音分，半音之间线性插值（误差小于 1 音分）
+pub fn transpose(freq: u32, cents: i32) -> u32 {
+    let octave = cents.div_euclid(1200);
+    let rem = cents.rem_euclid(1200) as u32;
+    let (semitone, frac) = ((rem / 100) as usize, rem % 100);
+
+    let lo = SEMITONE_RATIO[semitone];
+    let hi = SEMITONE_RATIO[semitone + 1];
+    let ratio = lo + (hi - lo) * frac / 100;
+
+    let freq = (freq as u64 * ratio as u64) >> 16;
+    let freq = if octave >= 0 {
+        freq << octave
+    } else {
+        freq >> -octave
+    };
+    freq.min(u32::MAX as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn octaves_are_exact() {
+        assert_eq!(transpose(440, 0), 440);
+        assert_eq!(transpose(440, 1200), 880);
+        assert_eq!(transpose(440, -1200), 220);
+        assert_eq!(transpose(440, 2400), 1760);
+    }
+
+    #[test]
+    fn semitones_follow_equal_temperament() {
+        // A4 上方五度是 E5 = 659.26Hz
+        assert_eq!(transpose(440 * FREQ_SCALE, 700) / FREQ_SCALE, 659);
+        // A4 下方半音是 G#4 = 415.30Hz
+        assert_eq!(transpose(440 * FREQ_SCALE, -100) / FREQ_SCALE, 415);
+    }
+
+    #[test]
+    fn monotonic_in_cents() {
+        for cents in -1200..1200 {
+            assert!(transpose(4400, cents) <= transpose(4400, cents + 1));
+        }
+    }
+}
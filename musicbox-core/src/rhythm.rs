@@ -0,0 +1,407 @@
+use heapless::Vec;
+
+/// 录音可选的音符时值，负数为附点
+const NOTE_VALUES: [i8; 8] = [1, -2, 2, -4, 4, -8, 8, 16];
+/// 一个全音符按这么多份精确计算拍点，附点和六十四分音符都能整除
+const WHOLE_UNITS: u32 = 384;
+
+/// 拍号：每小节 `beats` 拍，以 `unit` 分音符为一拍
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub beats: u32,
+    pub unit: u32,
+}
+
+impl Signature {
+    pub const ALL: [Signature; 5] = [
+        Signature { beats: 2, unit: 4 },
+        Signature { beats: 3, unit: 4 },
+        Signature { beats: 4, unit: 4 },
+        Signature { beats: 6, unit: 8 },
+        Signature { beats: 1, unit: 4 },
+    ];
+
+    /// 2/4 -> 3/4 -> 4/4 -> 6/8 -> 不分小节 -> 2/4
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// 曲子的节奏：全音符的时长、拍号和弱起。
+/// 按音符时值（负数为附点）的序列算时间、小节和拍子，小节线和拍点按时值精确计算，不受毫秒取整影响
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Meter {
+    /// 一个全音符的毫秒数
+    pub whole_ms: u32,
+    pub signature: Signature,
+    /// 弱起小节的时值，没有弱起时为 0
+    pub pickup: i8,
+}
+
+impl Meter {
+    /// 时值 `div` 的毫秒数
+    pub fn delay_ms(&self, div: i8) -> u32 {
+        let dotted = div < 0;
+        let div = div.unsigned_abs().max(1) as f32;
+        let delay_ms = self.whole_ms as f32 / div;
+        (if dotted { delay_ms * 1.5 } else { delay_ms }) as u32
+    }
+
+    /// 拍号中一拍的时长，6/8 拍为一个八分音符
+    pub fn beat_ms(&self) -> u32 {
+        self.whole_ms / self.signature.unit.max(1)
+    }
+
+    /// 第 `pos` 个音符开始的时间，超过曲尾时为整首的时长
+    pub fn time_at(&self, divs: impl IntoIterator<Item = i8>, pos: usize) -> u32 {
+        divs.into_iter()
+            .take(pos)
+            .map(|div| self.delay_ms(div))
+            .sum()
+    }
+
+    /// 第一个在曲子时间 `ms` 或之后开始的音符，超过曲尾时为音符数
+    pub fn pos_at(&self, divs: impl IntoIterator<Item = i8>, ms: u32) -> usize {
+        let mut time_ms = 0;
+        let mut len = 0;
+        for (pos, div) in divs.into_iter().enumerate() {
+            if time_ms >= ms {
+                return pos;
+            }
+            time_ms += self.delay_ms(div);
+            len = pos + 1;
+        }
+        len
+    }
+
+    /// 从曲子时间 `ms` 所在的小节向后（正数）或向前（负数）移动 `bars` 小节，
+    /// 返回该小节第一个音符的位置，超过曲尾时为音符数。弱起小节算作第 -1 小节
+    pub fn bar_pos(&self, divs: impl IntoIterator<Item = i8> + Clone, ms: u32, bars: i32) -> usize {
+        let bar_units = self.bar_units();
+        let pickup_units = self.pickup_units();
+        let bar_of = |units: u32| match units.checked_sub(pickup_units) {
+            Some(units) => (units / bar_units) as i32,
+            None => -1,
+        };
+
+        // 正在发声的音符所在的小节
+        let (mut time_ms, mut start_units, mut bar) = (0, 0, -1);
+        for div in divs.clone() {
+            if time_ms > ms {
+                break;
+            }
+            bar = bar_of(start_units);
+            time_ms += self.delay_ms(div);
+            start_units += units(div);
+        }
+
+        let target = bar.saturating_add(bars);
+        let target_units = match u32::try_from(target) {
+            Ok(target) => pickup_units.saturating_add(target.saturating_mul(bar_units)),
+            Err(_) => 0,
+        };
+        let mut start_units = 0;
+        let mut len = 0;
+        for (pos, div) in divs.into_iter().enumerate() {
+            if start_units >= target_units {
+                return pos;
+            }
+            start_units += units(div);
+            len = pos + 1;
+        }
+        len
+    }
+
+    /// 第 `pos` 个音符是否落在拍上：不在拍上为 `None`，落在小节第一拍为 `Some(true)`。
+    /// 按拍号分拍，弱起小节算作前一小节的末尾
+    pub fn beat_at(&self, divs: impl IntoIterator<Item = i8>, pos: usize) -> Option<bool> {
+        let units: u32 = divs.into_iter().take(pos).map(units).sum();
+        let bar_units = self.bar_units();
+        let offset = (bar_units - self.pickup_units() % bar_units) % bar_units;
+        let in_bar = (units + offset) % bar_units;
+        let beat_units = WHOLE_UNITS / self.signature.unit.max(1);
+        in_bar.is_multiple_of(beat_units).then_some(in_bar == 0)
+    }
+
+    /// 一小节的份数
+    fn bar_units(&self) -> u32 {
+        (WHOLE_UNITS * self.signature.beats / self.signature.unit.max(1)).max(1)
+    }
+
+    /// 弱起小节的份数，没有弱起时为 0
+    fn pickup_units(&self) -> u32 {
+        if self.pickup == 0 {
+            0
+        } else {
+            units(self.pickup)
+        }
+    }
+}
+
+/// 音符时值对应的份数，负数为附点
+fn units(div: i8) -> u32 {
+    let units = WHOLE_UNITS / div.unsigned_abs().max(1) as u32;
+    if div < 0 {
+        units * 3 / 2
+    } else {
+        units
+    }
+}
+
+/// 把按住和松开的毫秒数按每分钟 `bpm` 拍（四分音符为一拍）吸附成音符时值。
+/// 休止为 `rest`，不足半个十六分音符的休止略去，超过 `N` 个时值的部分丢弃
+pub fn quantize<T: Copy + PartialEq, const N: usize>(
+    events: &[(T, u32)],
+    rest: T,
+    bpm: u32,
+) -> Vec<(T, i8), N> {
+    let whole_ms = 60_000 * 4 / bpm.max(1);
+    let mut notes = Vec::new();
+    for &(tone, ms) in events {
+        if tone == rest && ms < duration_ms(16, whole_ms) / 2 {
+            continue;
+        }
+        for div in note_values(ms, whole_ms) {
+            if notes.push((tone, div)).is_err() {
+                return notes;
+            }
+        }
+    }
+    notes
+}
+
+/// 把毫秒数拆成音符时值：超过一个全音符的部分按全音符拆开，
+/// 余下的吸附到最近的时值，不足半个十六分音符的零头略去
+pub fn note_values(ms: u32, whole_ms: u32) -> impl Iterator<Item = i8> {
+    let wholes = ms.saturating_sub(whole_ms / 2) / whole_ms.max(1);
+    let rest_ms = ms - wholes * whole_ms;
+    let last = (rest_ms >= duration_ms(16, whole_ms) / 2 || wholes == 0).then(|| {
+        NOTE_VALUES
+            .iter()
+            .copied()
+            .min_by_key(|&div| duration_ms(div, whole_ms).abs_diff(rest_ms))
+            .unwrap_or(16)
+    });
+    (0..wholes).map(|_| 1).chain(last)
+}
+
+/// 时值 `div` 的毫秒数，负数为附点
+pub fn duration_ms(div: i8, whole_ms: u32) -> u32 {
+    let ms = whole_ms / div.unsigned_abs().max(1) as u32;
+    if div < 0 {
+        ms * 3 / 2
+    } else {
+        ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 120 BPM 的全音符
+    const WHOLE_MS: u32 = 2000;
+
+    fn values(ms: u32) -> std::vec::Vec<i8> {
+        note_values(ms, WHOLE_MS).collect()
+    }
+
+    #[test]
+    fn exact_values() {
+        assert_eq!(values(2000), [1]);
+        assert_eq!(values(1000), [2]);
+        assert_eq!(values(500), [4]);
+        assert_eq!(values(250), [8]);
+        assert_eq!(values(125), [16]);
+    }
+
+    #[test]
+    fn dotted_values() {
+        assert_eq!(values(1500), [-2]);
+        assert_eq!(values(750), [-4]);
+        assert_eq!(values(375), [-8]);
+    }
+
+    #[test]
+    fn snaps_to_nearest() {
+        assert_eq!(values(540), [4]);
+        assert_eq!(values(700), [-4]);
+        assert_eq!(values(1100), [2]);
+        // 再短也至少是一个十六分音符
+        assert_eq!(values(10), [16]);
+    }
+
+    #[test]
+    fn ties_longer_than_a_whole_note() {
+        assert_eq!(values(3000), [1, 2]);
+        assert_eq!(values(3500), [1, -2]);
+        assert_eq!(values(4000), [1, 1]);
+        assert_eq!(values(5000), [1, 1, 2]);
+        // 不足半个十六分音符的零头略去
+        assert_eq!(values(4050), [1, 1]);
+        assert_eq!(values(2900), [1]);
+    }
+
+    #[test]
+    fn short_rests_are_skipped() {
+        let events = [('C', 500), ('-', 50), ('D', 500), ('-', 70), ('E', 1000)];
+        let notes: Vec<_, 8> = quantize(&events, '-', 120);
+        assert_eq!(notes, [('C', 4), ('D', 4), ('-', 16), ('E', 2)]);
+    }
+
+    #[test]
+    fn short_notes_are_kept() {
+        let notes: Vec<_, 8> = quantize(&[('C', 20)], '-', 120);
+        assert_eq!(notes, [('C', 16)]);
+    }
+
+    #[test]
+    fn follows_tempo() {
+        let notes: Vec<_, 8> = quantize(&[('C', 500), ('D', 1000)], '-', 60);
+        assert_eq!(notes, [('C', 8), ('D', 4)]);
+    }
+
+    /// 生日歌：6/8 拍，附点四分音符的弱起，每分钟 140 个四分音符
+    const HAPPY_BIRTHDAY: [i8; 25] = [
+        4, 8, -4, -4, -4, -2, //
+        4, 8, -4, -4, -4, -2, //
+        4, 8, -4, -4, -4, -4, -4, //
+        4, 8, -4, -4, -4, -2,
+    ];
+    const HAPPY_BIRTHDAY_METER: Meter = Meter {
+        whole_ms: 60_000 * 4 / 140,
+        signature: Signature { beats: 6, unit: 8 },
+        pickup: -4,
+    };
+
+    /// 圣诞歌开头：3/4 拍，四分音符的弱起
+    const MERRY_CHRISTMAS: [i8; 16] = [4, 4, 8, 8, 8, 8, 4, 4, 4, 4, 8, 8, 8, 8, 4, 4];
+    const MERRY_CHRISTMAS_METER: Meter = Meter {
+        whole_ms: 60_000 * 4 / 140,
+        signature: Signature { beats: 3, unit: 4 },
+        pickup: 4,
+    };
+
+    #[test]
+    fn beats_in_compound_meter_with_dotted_pickup() {
+        let meter = HAPPY_BIRTHDAY_METER;
+        let beat = |pos| meter.beat_at(HAPPY_BIRTHDAY, pos);
+        // 弱起的两个音落在前一小节的第 4、6 个八分音符上
+        assert_eq!(beat(0), Some(false));
+        assert_eq!(beat(1), Some(false));
+        // 弱起之后是第一小节的重拍，下一个附点四分音符在小节中间
+        assert_eq!(beat(2), Some(true));
+        assert_eq!(beat(3), Some(false));
+        assert_eq!(beat(4), Some(true));
+        assert_eq!(meter.beat_ms(), meter.whole_ms / 8);
+    }
+
+    #[test]
+    fn beats_in_simple_meter_with_pickup() {
+        let meter = MERRY_CHRISTMAS_METER;
+        let beat = |pos| meter.beat_at(MERRY_CHRISTMAS, pos);
+        assert_eq!(beat(0), Some(false));
+        assert_eq!(beat(1), Some(true));
+        assert_eq!(beat(2), Some(false));
+        // 八分音符的后半拍不在拍上
+        assert_eq!(beat(3), None);
+        assert_eq!(beat(4), Some(false));
+        assert_eq!(beat(6), Some(true));
+        assert_eq!(beat(9), Some(true));
+        assert_eq!(meter.beat_ms(), meter.whole_ms / 4);
+    }
+
+    #[test]
+    fn bars_with_dotted_pickup() {
+        let meter = HAPPY_BIRTHDAY_METER;
+        let bar = |ms, bars| meter.bar_pos(HAPPY_BIRTHDAY, ms, bars);
+        // 从弱起开始：第 -1、0、1 小节
+        assert_eq!(bar(0, 0), 0);
+        assert_eq!(bar(0, 1), 2);
+        assert_eq!(bar(0, 2), 4);
+        // 在第 0 小节的第二个音上，回到小节开头、退回弱起
+        let ms = meter.time_at(HAPPY_BIRTHDAY, 3);
+        assert_eq!(bar(ms, 0), 2);
+        assert_eq!(bar(ms, -1), 0);
+        assert_eq!(bar(ms, -5), 0);
+    }
+
+    #[test]
+    fn bars_with_pickup() {
+        let meter = MERRY_CHRISTMAS_METER;
+        let bar = |ms, bars| meter.bar_pos(MERRY_CHRISTMAS, ms, bars);
+        assert_eq!(bar(0, 1), 1);
+        assert_eq!(bar(0, 2), 6);
+        assert_eq!(bar(0, 3), 9);
+        let ms = meter.time_at(MERRY_CHRISTMAS, 7);
+        assert_eq!(bar(ms, 0), 6);
+        assert_eq!(bar(ms, 1), 9);
+        assert_eq!(bar(ms, -1), 1);
+    }
+
+    #[test]
+    fn seeking_past_the_end() {
+        let meter = HAPPY_BIRTHDAY_METER;
+        let len = HAPPY_BIRTHDAY.len();
+        let duration = meter.time_at(HAPPY_BIRTHDAY, len);
+        assert_eq!(meter.time_at(HAPPY_BIRTHDAY, len + 10), duration);
+        assert_eq!(meter.pos_at(HAPPY_BIRTHDAY, duration + 1), len);
+        assert_eq!(meter.pos_at(HAPPY_BIRTHDAY, u32::MAX), len);
+        assert_eq!(meter.bar_pos(HAPPY_BIRTHDAY, 0, 100), len);
+        assert_eq!(meter.bar_pos(HAPPY_BIRTHDAY, u32::MAX, 1), len);
+        assert_eq!(meter.bar_pos(HAPPY_BIRTHDAY, u32::MAX, i32::MAX), len);
+        assert_eq!(meter.bar_pos([], 0, 1), 0);
+    }
+
+    #[test]
+    fn time_and_position_round_trip() {
+        for (divs, meter) in [
+            (&HAPPY_BIRTHDAY[..], HAPPY_BIRTHDAY_METER),
+            (&MERRY_CHRISTMAS[..], MERRY_CHRISTMAS_METER),
+        ] {
+            let divs = divs.iter().copied();
+            for pos in 0..=divs.len() {
+                let ms = meter.time_at(divs.clone(), pos);
+                assert_eq!(meter.pos_at(divs.clone(), ms), pos);
+            }
+            // 落在音符中间的时间取下一个音符
+            let duration = meter.time_at(divs.clone(), divs.len());
+            for ms in (0..duration).step_by(37) {
+                let pos = meter.pos_at(divs.clone(), ms);
+                assert!(meter.time_at(divs.clone(), pos) >= ms);
+                assert!(pos == 0 || meter.time_at(divs.clone(), pos - 1) < ms);
+            }
+        }
+    }
+
+    #[test]
+    fn signatures_cycle() {
+        let mut signature = Signature { beats: 4, unit: 4 };
+        for _ in 0..Signature::ALL.len() {
+            signature = signature.next();
+        }
+        assert_eq!(signature, Signature { beats: 4, unit: 4 });
+        assert_eq!(
+            Signature { beats: 6, unit: 8 }.next(),
+            Signature { beats: 1, unit: 4 }
+        );
+    }
+
+    #[test]
+    fn full_buffer() {
+        let events = [('C', 500); 64];
+        let notes: Vec<_, 64> = quantize(&events, '-', 120);
+        assert_eq!(notes.len(), 64);
+        assert!(notes.iter().all(|&note| note == ('C', 4)));
+
+        // 拆开的全音符放不下时截断，不会溢出
+        let events = [('C', 500), ('D', 2000 * 70)];
+        let notes: Vec<_, 64> = quantize(&events, '-', 120);
+        assert_eq!(notes.len(), 64);
+        assert_eq!(notes[0], ('C', 4));
+        assert!(notes[1..].iter().all(|&note| note == ('D', 1)));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::pitch::{transpose, FREQ_SCALE};
+
+/// 倾斜到 ±`TILT_RANGE_MG` 时到达音域或音量的两端
+const TILT_RANGE_MG: i32 = 700;
+/// 最低音，MIDI 音符号 C4
+const ROOT_NOTE: i32 = 60;
+const A4_NOTE: i32 = 69;
+/// 音域的八度数
+const OCTAVES: i32 = 2;
+/// 侧倾到底时的最小音量（百分比）
+const LEVEL_MIN: i32 = 10;
+
+/// 音阶：连续变化，或吸附到音阶内最近的音
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scale {
+    Continuous,
+    Chromatic,
+    Major,
+    Minor,
+    Pentatonic,
+}
+
+impl Scale {
+    /// 连续 -> 半音阶 -> 大调 -> 小调 -> 五声 -> 连续
+    pub fn next(self) -> Self {
+        match self {
+            Scale::Continuous => Scale::Chromatic,
+            Scale::Chromatic => Scale::Major,
+            Scale::Major => Scale::Minor,
+            Scale::Minor => Scale::Pentatonic,
+            Scale::Pentatonic => Scale::Continuous,
+        }
+    }
+
+    /// 一个八度内各音相对主音的半音数，连续时为空
+    fn degrees(self) -> &'static [i32] {
+        match self {
+            Scale::Continuous => &[],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9],
+        }
+    }
+}
+
+/// 倾斜乐器：前后倾斜（Y 轴）决定音高，左右侧倾（X 轴）决定音量，放平时最响
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theremin {
+    scale: Scale,
+    /// 发声开关，由按键控制
+    gate: bool,
+}
+
+impl Theremin {
+    pub fn new() -> Self {
+        Self {
+            scale: Scale::Continuous,
+            gate: false,
+        }
+    }
+
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+    }
+
+    pub fn gate(&self) -> bool {
+        self.gate
+    }
+
+    pub fn set_gate(&mut self, gate: bool) {
+        self.gate = gate;
+    }
+
+    /// 前后倾斜对应的音分，相对最低音，按音阶吸附
+    pub fn cents(&self, y_mg: i32) -> i32 {
+        let y = y_mg.clamp(-TILT_RANGE_MG, TILT_RANGE_MG) + TILT_RANGE_MG;
+        let cents = y * OCTAVES * 1200 / (2 * TILT_RANGE_MG);
+        quantize(cents, self.scale)
+    }
+
+    /// 前后倾斜对应的频率，单位 1/`FREQ_SCALE` Hz
+    pub fn freq(&self, y_mg: i32) -> u32 {
+        let cents = (ROOT_NOTE - A4_NOTE) * 100 + self.cents(y_mg);
+        transpose(440 * FREQ_SCALE, cents)
+    }
+
+    /// 左右侧倾对应的音量（百分比）
+    pub fn level(&self, x_mg: i32) -> u32 {
+        let x = x_mg.abs().min(TILT_RANGE_MG);
+        (100 - x * (100 - LEVEL_MIN) / TILT_RANGE_MG) as u32
+    }
+}
+
+impl Default for Theremin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 把音分吸附到音阶内最近的音，下一个八度的主音也算在内
+fn quantize(cents: i32, scale: Scale) -> i32 {
+    let degrees = scale.degrees();
+    if degrees.is_empty() {
+        return cents;
+    }
+    let octave = cents.div_euclid(1200) * 1200;
+    let within = cents.rem_euclid(1200);
+    let nearest = degrees
+        .iter()
+        .chain(&[12])
+        .map(|degree| degree * 100)
+        .min_by_key(|degree| (degree - within).abs())
+        .unwrap_or(0);
+    octave + nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALES: [Scale; 5] = [
+        Scale::Continuous,
+        Scale::Chromatic,
+        Scale::Major,
+        Scale::Minor,
+        Scale::Pentatonic,
+    ];
+
+    fn theremin(scale: Scale) -> Theremin {
+        let mut theremin = Theremin::new();
+        theremin.set_scale(scale);
+        theremin
+    }
+
+    #[test]
+    fn pitch_clamps_at_full_tilt() {
+        for scale in SCALES {
+            let theremin = theremin(scale);
+            assert_eq!(theremin.cents(-TILT_RANGE_MG), 0);
+            assert_eq!(theremin.cents(-2000), 0);
+            assert_eq!(theremin.cents(TILT_RANGE_MG), OCTAVES * 1200);
+            assert_eq!(theremin.cents(2000), OCTAVES * 1200);
+        }
+    }
+
+    #[test]
+    fn continuous_pitch_is_linear_in_tilt() {
+        let theremin = theremin(Scale::Continuous);
+        assert_eq!(theremin.cents(0), 1200);
+        assert_eq!(theremin.cents(-350), 600);
+        assert_eq!(theremin.cents(1), 1201);
+        for y in -TILT_RANGE_MG..TILT_RANGE_MG {
+            assert!(theremin.cents(y) <= theremin.cents(y + 1));
+        }
+    }
+
+    #[test]
+    fn snaps_to_every_degree_of_each_scale() {
+        for scale in SCALES.into_iter().skip(1) {
+            for &degree in scale.degrees() {
+                for octave in [0, 1200] {
+                    let cents = octave + degree * 100;
+                    // 偏离 40 音分以内吸附回来
+                    assert_eq!(quantize(cents + 40, scale), cents, "{scale:?} {cents}");
+                    assert_eq!(quantize(cents - 40, scale), cents, "{scale:?} {cents}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snapped_pitch_stays_in_scale() {
+        for scale in SCALES.into_iter().skip(1) {
+            for cents in 0..=2400 {
+                let snapped = quantize(cents, scale);
+                let degree = snapped.rem_euclid(1200) / 100;
+                assert_eq!(snapped % 100, 0);
+                assert!(
+                    scale.degrees().contains(&degree),
+                    "{scale:?} {cents} -> {snapped}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn snaps_up_to_the_octave_root() {
+        // 大调的 B（1100）和高八度的 C（1200）之间，过了中点吸附到高八度的主音
+        assert_eq!(quantize(1149, Scale::Major), 1100);
+        assert_eq!(quantize(1151, Scale::Major), 1200);
+        // 五声音阶最高是 A（900），A 和高八度的 C 之间的中点是 1050
+        assert_eq!(quantize(1049, Scale::Pentatonic), 900);
+        assert_eq!(quantize(1051, Scale::Pentatonic), 1200);
+        // 小调的降 B（1000）
+        assert_eq!(quantize(1101, Scale::Minor), 1200);
+        // 第二个八度同样
+        assert_eq!(quantize(2351, Scale::Major), 2400);
+        assert_eq!(theremin(Scale::Major).cents(690), 2400);
+    }
+
+    #[test]
+    fn continuous_does_not_snap() {
+        for cents in [0, 1, 49, 50, 1151, 2399] {
+            assert_eq!(quantize(cents, Scale::Continuous), cents);
+        }
+    }
+
+    #[test]
+    fn root_is_middle_c() {
+        let theremin = theremin(Scale::Continuous);
+        // C4 = 261.63Hz，误差小于 1 音分
+        let freq = theremin.freq(-TILT_RANGE_MG);
+        assert!(
+            freq.abs_diff(26163 * FREQ_SCALE / 100) < FREQ_SCALE,
+            "{freq}"
+        );
+        assert_eq!(
+            theremin.freq(TILT_RANGE_MG),
+            theremin.freq(-TILT_RANGE_MG) * 4
+        );
+    }
+
+    #[test]
+    fn level_curve() {
+        let theremin = Theremin::new();
+        assert_eq!(theremin.level(0), 100);
+        assert_eq!(theremin.level(TILT_RANGE_MG), LEVEL_MIN as u32);
+        assert_eq!(theremin.level(-TILT_RANGE_MG), LEVEL_MIN as u32);
+        assert_eq!(theremin.level(2000), LEVEL_MIN as u32);
+        assert_eq!(theremin.level(-2000), LEVEL_MIN as u32);
+        assert_eq!(theremin.level(350), 55);
+        for x in 0..TILT_RANGE_MG {
+            assert!(theremin.level(x) >= theremin.level(x + 1));
+            assert_eq!(theremin.level(x), theremin.level(-x));
+        }
+    }
+
+    #[test]
+    fn scales_cycle() {
+        let mut scale = Scale::Continuous;
+        for expected in SCALES.into_iter().cycle().skip(1).take(5) {
+            scale = scale.next();
+            assert_eq!(scale, expected);
+        }
+    }
+}
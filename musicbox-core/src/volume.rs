@@ -0,0 +1,211 @@
+/// 增益的满量程，千分比
+pub const GAIN_MAX: u32 = 1000;
+/// 校准表的点数：增益 0%、10%……100%
+pub const CALIBRATION_POINTS: usize = 11;
+/// 校准表保存到 flash 的字数，每个字放两个点
+pub const CALIBRATION_WORDS: usize = CALIBRATION_POINTS.div_ceil(2);
+/// 占空比的满量程，千分比
+const DUTY_MAX: u16 = 1000;
+
+/// 音量曲线：把 0-100 的音量换算成振幅增益（0-`GAIN_MAX`），音量 0 为真正的静音
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// 振幅与音量成正比
+    Linear,
+    /// 对数曲线，0-100 对应 -40dB 到 0dB，每一档听起来变化相近
+    Perceptual,
+    /// 三次方曲线，介于两者之间
+    Cubic,
+}
+
+impl Curve {
+    /// 音量 0-100 对应的增益
+    pub fn gain(&self, volume: u32) -> u32 {
+        if volume == 0 {
+            return 0;
+        }
+        let table = match self {
+            Curve::Linear => &[0, 100, 200, 300, 400, 500, 600, 700, 800, 900, 1000],
+            // 10^(2 * (v - 1))
+            Curve::Perceptual => &[10, 16, 25, 40, 63, 100, 158, 251, 398, 631, 1000],
+            Curve::Cubic => &[0, 1, 8, 27, 64, 125, 216, 343, 512, 729, 1000],
+        };
+        interpolate(table, volume.min(100) * GAIN_MAX / 100).max(1)
+    }
+}
+
+/// 每块板子的校准表：增益 0%、10%……100% 时方波的占空比（千分比）。
+/// 扬声器和驱动电路不同，相同占空比的响度也不同，可以逐档实测后替换
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration(pub [u16; CALIBRATION_POINTS]);
+
+impl Calibration {
+    /// 理想扬声器：方波基波振幅与 sin(π·占空比) 成正比，50% 最响
+    pub const DEFAULT: Calibration =
+        Calibration([0, 32, 64, 97, 131, 167, 205, 247, 295, 356, 500]);
+
+    /// 增益对应的占空比计数，`top` 为一个周期的计数
+    pub fn duty(&self, gain: u32, top: u32) -> u32 {
+        if gain == 0 {
+            return 0;
+        }
+        top * interpolate(&self.0, gain.min(GAIN_MAX)) / 1000
+    }
+
+    /// 修改第 `point` 个点（增益 `point` * 10%）的占空比，
+    /// 超出范围或使表不再单调递增时返回 `false` 且不修改
+    pub fn set(&mut self, point: usize, duty: u16) -> bool {
+        let mut table = self.0;
+        let Some(entry) = table.get_mut(point) else {
+            return false;
+        };
+        *entry = duty;
+        let valid = duty <= DUTY_MAX && table.windows(2).all(|pair| pair[0] <= pair[1]);
+        if valid {
+            self.0 = table;
+        }
+        valid
+    }
+
+    /// 编码成保存到 flash 的字
+    pub fn to_words(self) -> [u32; CALIBRATION_WORDS] {
+        let mut words = [0; CALIBRATION_WORDS];
+        for (word, pair) in words.iter_mut().zip(self.0.chunks(2)) {
+            *word = pair
+                .iter()
+                .rev()
+                .fold(0, |word, &duty| word << 16 | duty as u32);
+        }
+        words
+    }
+
+    /// 从 flash 中读出的字恢复，内容无效时为空
+    pub fn from_words(words: &[u32; CALIBRATION_WORDS]) -> Option<Self> {
+        let mut calibration = Self([0; CALIBRATION_POINTS]);
+        for (point, duty) in calibration.0.iter_mut().enumerate() {
+            *duty = (words[point / 2] >> (point % 2 * 16)) as u16;
+        }
+        let valid = calibration.0.iter().all(|&duty| duty <= DUTY_MAX)
+            && calibration.0.windows(2).all(|pair| pair[0] <= pair[1]);
+        valid.then_some(calibration)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// 在 11 个等距点之间线性插值，`x` 取值 0-`GAIN_MAX`
+fn interpolate(table: &[u16; CALIBRATION_POINTS], x: u32) -> u32 {
+    let step = GAIN_MAX / 10;
+    let index = (x / step).min(9) as usize;
+    let a = table[index] as u32;
+    let b = table[index + 1] as u32;
+    let frac = x - index as u32 * step;
+    if b >= a {
+        a + (b - a) * frac / step
+    } else {
+        a - (a - b) * frac / step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 3] = [Curve::Linear, Curve::Perceptual, Curve::Cubic];
+
+    #[test]
+    fn endpoints() {
+        for curve in CURVES {
+            assert_eq!(curve.gain(0), 0, "{curve:?} is silent at 0");
+            assert_eq!(curve.gain(100), GAIN_MAX, "{curve:?} is full scale at 100");
+            assert!(curve.gain(1) >= 1, "{curve:?} is audible at 1");
+            // 超过 100 按 100 算
+            assert_eq!(curve.gain(150), GAIN_MAX);
+        }
+    }
+
+    #[test]
+    fn curves_are_monotonic() {
+        for curve in CURVES {
+            for volume in 0..100 {
+                assert!(
+                    curve.gain(volume) <= curve.gain(volume + 1),
+                    "{curve:?} drops at {volume}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn linear_is_proportional() {
+        for volume in 0..=100 {
+            assert_eq!(Curve::Linear.gain(volume), volume * 10);
+        }
+    }
+
+    #[test]
+    fn perceptual_is_20db_per_half() {
+        // -40dB 到 0dB：一半音量为 -20dB，即 1/10 振幅
+        assert_eq!(Curve::Perceptual.gain(50), 100);
+        assert_eq!(Curve::Perceptual.gain(1), 10);
+        // 每 10 档约 4dB（约 1.585 倍，表值取整有误差）
+        for volume in (10..=90).step_by(10) {
+            let ratio = Curve::Perceptual.gain(volume + 10) * 1000 / Curve::Perceptual.gain(volume);
+            assert!((1550..=1610).contains(&ratio), "ratio {ratio} at {volume}");
+        }
+    }
+
+    #[test]
+    fn cubic_is_cube_of_volume() {
+        for volume in (10..=100).step_by(10) {
+            assert_eq!(Curve::Cubic.gain(volume), volume * volume * volume / 1000);
+        }
+        assert!(Curve::Cubic.gain(50) < Curve::Linear.gain(50));
+        assert!(Curve::Cubic.gain(50) > Curve::Perceptual.gain(50));
+    }
+
+    #[test]
+    fn calibration_endpoints_and_interpolation() {
+        let calibration = Calibration::DEFAULT;
+        let top = 1000;
+        assert_eq!(calibration.duty(0, top), 0);
+        assert_eq!(calibration.duty(GAIN_MAX, top), 500);
+        assert_eq!(calibration.duty(GAIN_MAX * 2, top), 500);
+        assert_eq!(calibration.duty(100, top), 32);
+        // 两点之间线性插值
+        assert_eq!(calibration.duty(950, top), (356 + 500) / 2);
+        for gain in 0..GAIN_MAX {
+            assert!(calibration.duty(gain, top) <= calibration.duty(gain + 1, top));
+        }
+    }
+
+    #[test]
+    fn calibration_set_keeps_table_monotonic() {
+        let mut calibration = Calibration::DEFAULT;
+        assert!(calibration.set(5, 150));
+        assert_eq!(calibration.0[5], 150);
+        assert!(!calibration.set(5, 400), "above the next point");
+        assert!(!calibration.set(11, 0), "no such point");
+        assert!(!calibration.set(10, 1001), "above full scale");
+        assert_eq!(calibration.0[5], 150);
+    }
+
+    #[test]
+    fn calibration_words_round_trip() {
+        let mut calibration = Calibration::DEFAULT;
+        calibration.set(10, 480);
+        let words = calibration.to_words();
+        assert_eq!(words[0], 32 << 16);
+        assert_eq!(Calibration::from_words(&words), Some(calibration));
+        assert_eq!(
+            Calibration::from_words(&[u32::MAX; CALIBRATION_WORDS]),
+            None
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! WAV -> 8 位无符号、单声道 PCM，供 `build.rs` 在主机上转换 `assets/samples` 里的采样
+
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+
+/// WAV（PCM 编码，8/16 位，单声道或立体声，任意采样率）混为单声道，
+/// 线性插值重采样到 `sample_rate`，再量化成 8 位无符号 PCM
+pub fn convert(wav: &[u8], sample_rate: u32) -> Result<Vec<u8>, String> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err("not a RIFF/WAVE file".into());
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut chunks = &wav[12..];
+    while chunks.len() >= 8 {
+        let id = &chunks[0..4];
+        let len = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+        let body = chunks.get(8..8 + len).ok_or("truncated chunk")?;
+        match id {
+            b"fmt " => format = Some(body),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // 块按偶数字节对齐
+        chunks = chunks.get(8 + len + (len & 1)..).unwrap_or_default();
+    }
+
+    let format = format.ok_or("missing fmt chunk")?;
+    let data = data.ok_or("missing data chunk")?;
+    if format.len() < 16 {
+        return Err("fmt chunk too short".into());
+    }
+    let encoding = u16::from_le_bytes([format[0], format[1]]);
+    let channels = u16::from_le_bytes([format[2], format[3]]) as usize;
+    let rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let bits = u16::from_le_bytes([format[14], format[15]]);
+    if encoding != 1 {
+        return Err(format!(
+            "unsupported encoding {encoding}, only PCM is supported"
+        ));
+    }
+    if channels == 0 || rate == 0 || sample_rate == 0 {
+        return Err("invalid channel count or sample rate".into());
+    }
+
+    // 解码并混成单声道，取值 -1.0..1.0
+    let width = match bits {
+        8 => 1,
+        16 => 2,
+        _ => return Err(format!("unsupported bit depth {bits}")),
+    };
+    let mono: Vec<f32> = data
+        .chunks_exact(width * channels)
+        .map(|frame| {
+            let sum: f32 = frame
+                .chunks_exact(width)
+                .map(|s| match width {
+                    1 => (s[0] as f32 - 128.0) / 128.0,
+                    _ => i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+                })
+                .sum();
+            sum / channels as f32
+        })
+        .collect();
+
+    // 线性插值重采样
+    let len = (mono.len() as u64 * sample_rate as u64 / rate as u64) as usize;
+    let pcm = (0..len)
+        .map(|i| {
+            let pos = i as f32 * rate as f32 / sample_rate as f32;
+            let index = pos as usize;
+            let frac = pos - index as f32;
+            let a = mono[index];
+            let b = mono.get(index + 1).copied().unwrap_or(a);
+            let value = a + (b - a) * frac;
+            (value * 127.0 + 128.0).round().clamp(0.0, 255.0) as u8
+        })
+        .collect();
+    Ok(pcm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 拼出一个 PCM 编码的 WAV 文件
+    fn wav(channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let align = channels * bits / 8;
+        let mut fmt = Vec::new();
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&rate.to_le_bytes());
+        fmt.extend_from_slice(&(rate * align as u32).to_le_bytes());
+        fmt.extend_from_slice(&align.to_le_bytes());
+        fmt.extend_from_slice(&bits.to_le_bytes());
+
+        let mut body = Vec::new();
+        body.extend_from_slice(b"WAVE");
+        for (id, chunk) in [(b"fmt ", &fmt[..]), (b"data", data)] {
+            body.extend_from_slice(id);
+            body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            body.extend_from_slice(chunk);
+            if chunk.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut file = Vec::new();
+        file.extend_from_slice(b"RIFF");
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend_from_slice(&body);
+        file
+    }
+
+    fn samples16(values: &[i16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn mono_8_bit() {
+        // 输出按 ±127 量化，所以满幅输入会略微收窄
+        let data = [128, 255, 0, 64, 192];
+        let pcm = convert(&wav(1, 8000, 8, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 254, 1, 65, 192]);
+    }
+
+    #[test]
+    fn mono_16_bit() {
+        let data = samples16(&[0, i16::MAX, i16::MIN, 16384, -16384]);
+        let pcm = convert(&wav(1, 8000, 16, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 255, 1, 192, 65]);
+    }
+
+    #[test]
+    fn stereo_is_mixed_down() {
+        // 左右声道取平均：相反的两路抵消，相同的两路不变
+        let data = samples16(&[16384, -16384, 16384, 16384, -16384, -16384]);
+        let pcm = convert(&wav(2, 8000, 16, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 192, 65]);
+
+        let data = [255, 1, 192, 192];
+        let pcm = convert(&wav(2, 8000, 8, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 192]);
+    }
+
+    #[test]
+    fn resamples_other_rates() {
+        // 16kHz 隔一个取一个
+        let data = samples16(&[0, 16384, 0, 16384, 0, 16384]);
+        let pcm = convert(&wav(1, 16000, 16, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 128, 128]);
+
+        // 4kHz 在相邻采样之间插值
+        let data = [128, 192, 128];
+        let pcm = convert(&wav(1, 4000, 8, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 160, 192, 160, 128, 128]);
+    }
+
+    #[test]
+    fn odd_data_chunk_is_padded() {
+        let data = [128, 192, 128];
+        let pcm = convert(&wav(1, 8000, 8, &data), 8000).unwrap();
+        assert_eq!(pcm, [128, 192, 128]);
+    }
+
+    #[test]
+    fn rejects_truncated_data_chunk() {
+        let mut file = wav(1, 8000, 16, &samples16(&[0, 1, 2, 3]));
+        file.truncate(file.len() - 3);
+        assert_eq!(convert(&file, 8000), Err("truncated chunk".into()));
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        assert!(convert(b"not a wav file", 8000).is_err());
+        assert!(convert(&wav(1, 8000, 24, &[0; 6]), 8000).is_err());
+        assert!(convert(&wav(0, 8000, 8, &[0; 6]), 8000).is_err());
+
+        let mut float = wav(1, 8000, 16, &[0; 4]);
+        // fmt 块的编码字段改成 3（浮点）
+        float[20] = 3;
+        assert!(convert(&float, 8000).is_err());
+
+        let mut no_data = wav(1, 8000, 8, &[]);
+        no_data.truncate(no_data.len() - 8);
+        assert_eq!(convert(&no_data, 8000), Err("missing data chunk".into()));
+    }
+}
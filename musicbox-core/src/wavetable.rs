@@ -0,0 +1,168 @@
+use crate::pitch::FREQ_SCALE;
+
+/// 波表长度，必须是 2 的幂
+pub const TABLE_LEN: usize = 64;
+const INDEX_BITS: u32 = TABLE_LEN.trailing_zeros();
+
+/// 一个周期的波形，取值 -127..=127
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Wavetable(pub [i8; TABLE_LEN]);
+
+#[rustfmt::skip]
+pub const SINE: Wavetable = Wavetable([
+    0, 12, 25, 37, 49, 60, 71, 81, 90, 98, 106, 112, 117, 122, 125, 126,
+    127, 126, 125, 122, 117, 112, 106, 98, 90, 81, 71, 60, 49, 37, 25, 12,
+    0, -12, -25, -37, -49, -60, -71, -81, -90, -98, -106, -112, -117, -122, -125, -126,
+    -127, -126, -125, -122, -117, -112, -106, -98, -90, -81, -71, -60, -49, -37, -25, -12,
+]);
+
+#[rustfmt::skip]
+pub const TRIANGLE: Wavetable = Wavetable([
+    0, 8, 16, 24, 32, 40, 48, 56, 64, 71, 79, 87, 95, 103, 111, 119,
+    127, 119, 111, 103, 95, 87, 79, 71, 64, 56, 48, 40, 32, 24, 16, 8,
+    0, -8, -16, -24, -32, -40, -48, -56, -64, -71, -79, -87, -95, -103, -111, -119,
+    -127, -119, -111, -103, -95, -87, -79, -71, -64, -56, -48, -40, -32, -24, -16, -8,
+]);
+
+/// 基音加 1/2 幅度的二次谐波和 1/4 幅度的三次谐波
+#[rustfmt::skip]
+pub const ORGAN: Wavetable = Wavetable([
+    0, 25, 48, 70, 89, 104, 116, 123, 127, 127, 123, 117, 108, 99, 88, 78,
+    69, 60, 53, 48, 44, 41, 38, 37, 35, 33, 31, 28, 24, 19, 13, 7,
+    0, -7, -13, -19, -24, -28, -31, -33, -35, -37, -38, -41, -44, -48, -53, -60,
+    -69, -78, -88, -99, -108, -117, -123, -127, -127, -123, -116, -104, -89, -70, -48, -25,
+]);
+
+/// 音色的波形，每首曲子选择一种
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wave {
+    /// PWM 直接输出的方波
+    Square,
+    Sine,
+    Triangle,
+    Custom(&'static Wavetable),
+}
+
+impl Wave {
+    /// 方波返回 `None`，其余由波表振荡器合成
+    pub fn table(&self) -> Option<&'static Wavetable> {
+        match *self {
+            Wave::Square => None,
+            Wave::Sine => Some(&SINE),
+            Wave::Triangle => Some(&TRIANGLE),
+            Wave::Custom(table) => Some(table),
+        }
+    }
+}
+
+/// 定点相位累加振荡器：32 位相位的高 `INDEX_BITS` 位是波表下标，其余位用于插值
+pub struct Oscillator {
+    phase: u32,
+    increment: u32,
+}
+
+impl Oscillator {
+    pub const fn new() -> Self {
+        Self {
+            phase: 0,
+            increment: 0,
+        }
+    }
+
+    /// 设置频率（1/`FREQ_SCALE` Hz），相位保持连续，改变频率不会产生爆音
+    pub fn set_freq(&mut self, freq: u32, sample_rate: u32) {
+        let increment = ((freq as u64) << 32) / (sample_rate as u64 * FREQ_SCALE as u64);
+        self.increment = increment.min(u32::MAX as u64) as u32;
+    }
+
+    /// 输出下一个采样（-127..=127），相邻表项之间线性插值
+    pub fn next(&mut self, table: &Wavetable) -> i8 {
+        let index = (self.phase >> (32 - INDEX_BITS)) as usize;
+        let frac = ((self.phase >> (32 - INDEX_BITS - 8)) & 0xFF) as i32;
+        let a = table.0[index] as i32;
+        let b = table.0[(index + 1) % TABLE_LEN] as i32;
+        self.phase = self.phase.wrapping_add(self.increment);
+        (a + (b - a) * frac / 256) as i8
+    }
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    #[test]
+    fn increment_is_frequency_over_sample_rate() {
+        let mut osc = Oscillator::new();
+        osc.set_freq(440 * FREQ_SCALE, SAMPLE_RATE);
+        assert_eq!(osc.increment, ((440u64 << 32) / SAMPLE_RATE as u64) as u32);
+
+        // 1/FREQ_SCALE Hz 的精度
+        osc.set_freq(440 * FREQ_SCALE + 1, SAMPLE_RATE);
+        let fine = ((440 * FREQ_SCALE as u64 + 1) << 32) / (SAMPLE_RATE * FREQ_SCALE) as u64;
+        assert_eq!(osc.increment, fine as u32);
+    }
+
+    #[test]
+    fn increment_saturates_above_sample_rate() {
+        let mut osc = Oscillator::new();
+        osc.set_freq(SAMPLE_RATE * 2 * FREQ_SCALE, SAMPLE_RATE);
+        assert_eq!(osc.increment, u32::MAX);
+    }
+
+    #[test]
+    fn zero_frequency_holds_phase() {
+        let mut osc = Oscillator::new();
+        osc.set_freq(0, SAMPLE_RATE);
+        for _ in 0..10 {
+            assert_eq!(osc.next(&SINE), 0);
+        }
+        assert_eq!(osc.phase, 0);
+    }
+
+    #[test]
+    fn one_entry_per_sample_walks_the_table() {
+        let mut osc = Oscillator::new();
+        osc.set_freq(SAMPLE_RATE / TABLE_LEN as u32 * FREQ_SCALE, SAMPLE_RATE);
+        assert_eq!(osc.increment, 1 << (32 - INDEX_BITS));
+        for &expected in SINE.0.iter() {
+            assert_eq!(osc.next(&SINE), expected);
+        }
+        // 正好一个周期后相位回到 0
+        assert_eq!(osc.phase, 0);
+        assert_eq!(osc.next(&SINE), SINE.0[0]);
+    }
+
+    #[test]
+    fn phase_wraps_and_interpolates_past_the_last_entry() {
+        let mut osc = Oscillator::new();
+        // 最后一个表项和第一个表项的正中间
+        osc.phase = u32::MAX - (1 << (32 - INDEX_BITS - 1)) + 1;
+        osc.increment = 1 << (32 - INDEX_BITS);
+        let last = TRIANGLE.0[TABLE_LEN - 1] as i32;
+        let first = TRIANGLE.0[0] as i32;
+        assert_eq!(osc.next(&TRIANGLE) as i32, (last + first) / 2);
+        assert_eq!(osc.phase, 1 << (32 - INDEX_BITS - 1));
+    }
+
+    #[test]
+    fn phase_stays_continuous_when_frequency_changes() {
+        let mut osc = Oscillator::new();
+        osc.set_freq(440 * FREQ_SCALE, SAMPLE_RATE);
+        for _ in 0..5 {
+            osc.next(&SINE);
+        }
+        let phase = osc.phase;
+        osc.set_freq(880 * FREQ_SCALE, SAMPLE_RATE);
+        assert_eq!(osc.phase, phase);
+    }
+}
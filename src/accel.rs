@@ -1,3 +1,4 @@
+use defmt::Format;
 use fugit::{ExtU64, TimerDurationU64, TimerInstantU64};
 use lsm303agr::interface::I2cInterface;
 use lsm303agr::mode::MagOneShot;
@@ -11,6 +12,20 @@ enum ShakeState {
     Debounce,
 }
 
+// 左右倾斜方向
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Tilt {
+    Left,
+    Right,
+}
+
+// 板子的姿态：X 轴为左右侧倾，Y 轴为前后倾斜，单位 mg
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct Attitude {
+    pub x_mg: i32,
+    pub y_mg: i32,
+}
+
 pub struct Accel<T: embedded_hal::i2c::I2c, const TIMER_HZ: u32> {
     // 传感器实例
     sensor: Lsm303agr<I2cInterface<T>, MagOneShot>,
@@ -32,6 +47,18 @@ pub struct Accel<T: embedded_hal::i2c::I2c, const TIMER_HZ: u32> {
     hit_count_threshold: u32,
     // 参数：去抖时间
     debounce_duration: TimerDurationU64<TIMER_HZ>,
+    // 当前倾斜方向及下一次重复触发的时刻
+    tilt: Option<(Tilt, TimerInstantU64<TIMER_HZ>)>,
+    // 倾斜回调函数
+    tilt_event_fn: Option<fn(Tilt)>,
+    // 姿态回调函数，每次有新数据时调用
+    attitude_event_fn: Option<fn(Attitude)>,
+    // 参数：X 轴超过该值（mg）算倾斜
+    tilt_mg: i32,
+    // 参数：X 轴回到该值（mg）以内算放平
+    level_mg: i32,
+    // 参数：保持倾斜时重复触发的间隔
+    tilt_repeat: TimerDurationU64<TIMER_HZ>,
 }
 
 impl<T: embedded_hal::i2c::I2c, const TIMER_HZ: u32> Accel<T, TIMER_HZ> {
@@ -47,6 +74,12 @@ impl<T: embedded_hal::i2c::I2c, const TIMER_HZ: u32> Accel<T, TIMER_HZ> {
             time_window: 300.millis(),
             hit_count_threshold: 2,
             debounce_duration: 200.millis(),
+            tilt: None,
+            tilt_event_fn: None,
+            attitude_event_fn: None,
+            tilt_mg: 500,
+            level_mg: 250,
+            tilt_repeat: 400.millis(),
         }
     }
 
@@ -54,10 +87,25 @@ impl<T: embedded_hal::i2c::I2c, const TIMER_HZ: u32> Accel<T, TIMER_HZ> {
         self.shake_event_fn = Some(f);
     }
 
+    pub fn attach_tilt_event(&mut self, f: fn(Tilt)) {
+        self.tilt_event_fn = Some(f);
+    }
+
+    pub fn attach_attitude_event(&mut self, f: fn(Attitude)) {
+        self.attitude_event_fn = Some(f);
+    }
+
     pub fn tick(&mut self, now: &TimerInstantU64<TIMER_HZ>) {
         let Some(accel) = self.accel_new_data() else {
             return;
         };
+        self.check_tilt(&accel, now);
+        if let Some(callback) = self.attitude_event_fn {
+            callback(Attitude {
+                x_mg: accel.x_mg(),
+                y_mg: accel.y_mg(),
+            });
+        }
 
         if let Some(last_accel) = self.last_accel {
             let delta_sq = self.diff_square(accel, last_accel);
@@ -111,6 +159,30 @@ impl<T: embedded_hal::i2c::I2c, const TIMER_HZ: u32> Accel<T, TIMER_HZ> {
         self.last_accel = Some(accel);
     }
 
+    // 倾斜时触发一次，保持倾斜则按间隔重复，放平后重新开始
+    fn check_tilt(&mut self, accel: &Acceleration, now: &TimerInstantU64<TIMER_HZ>) {
+        let x = accel.x_mg();
+        let tilt = if x >= self.tilt_mg {
+            Some(Tilt::Right)
+        } else if x <= -self.tilt_mg {
+            Some(Tilt::Left)
+        } else {
+            None
+        };
+
+        match (tilt, self.tilt) {
+            (Some(tilt), Some((last, repeat_at))) if tilt == last && *now < repeat_at => {}
+            (Some(tilt), _) => {
+                if let Some(callback) = self.tilt_event_fn {
+                    callback(tilt);
+                }
+                self.tilt = Some((tilt, *now + self.tilt_repeat));
+            }
+            (None, _) if x.abs() <= self.level_mg => self.tilt = None,
+            (None, _) => {}
+        }
+    }
+
     // 获取加速度数据
     fn accel_new_data(&mut self) -> Option<Acceleration> {
         if self.sensor.accel_status().unwrap().xyz_new_data() {
@@ -0,0 +1,184 @@
+use defmt::Format;
+
+use crate::clock::Time;
+
+/// 闹钟的个数
+pub const ALARM_SLOTS: usize = 4;
+/// 保存到 flash 的字数：每个闹钟一个字，再加一个整点报时开关
+pub const ALARM_WORDS: usize = ALARM_SLOTS + 1;
+/// 贪睡的分钟数
+pub const SNOOZE_MINUTES: u32 = 9;
+/// 响铃无人理会多久后自动停止
+const RING_MINUTES: u32 = 15;
+const MINUTES_PER_DAY: u32 = 24 * 60;
+/// flash 中表示“没有设置”的字（擦除后的值）
+const EMPTY: u32 = u32::MAX;
+
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// 没有这个闹钟
+    NoSuchSlot(usize),
+}
+
+/// 每天 `hour:minute` 响起，播放列表中第 `song` 首
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alarm {
+    pub hour: u8,
+    pub minute: u8,
+    pub song: usize,
+}
+
+impl Alarm {
+    fn minute_of_day(&self) -> u32 {
+        self.hour as u32 * 60 + self.minute as u32
+    }
+
+    fn to_word(self) -> u32 {
+        (self.song as u32) << 16 | (self.hour as u32) << 8 | self.minute as u32
+    }
+
+    fn from_word(word: u32) -> Option<Self> {
+        if word == EMPTY {
+            return None;
+        }
+        let hour = (word >> 8) as u8;
+        let minute = word as u8;
+        (hour < 24 && minute < 60).then_some(Self {
+            hour,
+            minute,
+            song: (word >> 16) as usize,
+        })
+    }
+}
+
+/// 到点要做的事
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// 渐强播放第 `song` 首
+    Ring(usize),
+    /// 整点报时
+    Chime(u8),
+    /// 响铃太久没人理会，停止
+    Silence,
+}
+
+/// 闹钟调度：每分钟检查一次闹钟、贪睡和整点报时
+pub struct AlarmClock {
+    alarms: [Option<Alarm>; ALARM_SLOTS],
+    chime: bool,
+    /// 正在响的曲目及开始响的分钟
+    ringing: Option<(usize, u32)>,
+    /// 贪睡结束的分钟及要再响的曲目
+    snooze: Option<(u32, usize)>,
+    last_minute: Option<u32>,
+}
+
+impl AlarmClock {
+    pub fn new() -> Self {
+        Self {
+            alarms: [None; ALARM_SLOTS],
+            chime: false,
+            ringing: None,
+            snooze: None,
+            last_minute: None,
+        }
+    }
+
+    pub fn alarms(&self) -> &[Option<Alarm>; ALARM_SLOTS] {
+        &self.alarms
+    }
+
+    /// 设置或清除（`None`）第 `slot` 个闹钟
+    pub fn set(&mut self, slot: usize, alarm: Option<Alarm>) -> Result<(), Error> {
+        let entry = self.alarms.get_mut(slot).ok_or(Error::NoSuchSlot(slot))?;
+        *entry = alarm;
+        Ok(())
+    }
+
+    pub fn chime(&self) -> bool {
+        self.chime
+    }
+
+    pub fn set_chime(&mut self, chime: bool) {
+        self.chime = chime;
+    }
+
+    /// 是否有需要按时唤醒的闹钟、贪睡或整点报时
+    pub fn is_armed(&self) -> bool {
+        self.chime || self.snooze.is_some() || self.alarms.iter().any(Option::is_some)
+    }
+
+    pub fn is_ringing(&self) -> bool {
+        self.ringing.is_some()
+    }
+
+    /// 每秒调用，进入新的一分钟时返回要做的事
+    pub fn tick(&mut self, now: Time) -> Option<Action> {
+        let minute = now.minute_of_day();
+        if self.last_minute == Some(minute) {
+            return None;
+        }
+        self.last_minute = Some(minute);
+
+        if let Some((_, started)) = self.ringing {
+            if (minute + MINUTES_PER_DAY - started) % MINUTES_PER_DAY >= RING_MINUTES {
+                self.ringing = None;
+                return Some(Action::Silence);
+            }
+            return None;
+        }
+
+        let song = match self.snooze {
+            Some((at, song)) if at == minute => {
+                self.snooze = None;
+                Some(song)
+            }
+            _ => self
+                .alarms
+                .iter()
+                .flatten()
+                .find(|alarm| alarm.minute_of_day() == minute)
+                .map(|alarm| alarm.song),
+        };
+        if let Some(song) = song {
+            self.ringing = Some((song, minute));
+            return Some(Action::Ring(song));
+        }
+
+        (self.chime && now.minute == 0).then_some(Action::Chime(now.hour))
+    }
+
+    /// 停止响铃，`SNOOZE_MINUTES` 分钟后再响
+    pub fn snooze(&mut self, now: Time) {
+        if let Some((song, _)) = self.ringing.take() {
+            let at = (now.minute_of_day() + SNOOZE_MINUTES) % MINUTES_PER_DAY;
+            self.snooze = Some((at, song));
+        }
+    }
+
+    /// 停止响铃，取消贪睡
+    pub fn dismiss(&mut self) {
+        self.ringing = None;
+        self.snooze = None;
+    }
+
+    /// 编码成保存到 flash 的字
+    pub fn to_words(&self) -> [u32; ALARM_WORDS] {
+        let mut words = [EMPTY; ALARM_WORDS];
+        for (word, alarm) in words.iter_mut().zip(&self.alarms) {
+            if let Some(alarm) = alarm {
+                *word = alarm.to_word();
+            }
+        }
+        words[ALARM_SLOTS] = self.chime as u32;
+        words
+    }
+
+    /// 从 flash 中读出的字恢复闹钟设置
+    pub fn load(&mut self, words: &[u32; ALARM_WORDS]) {
+        for (alarm, &word) in self.alarms.iter_mut().zip(words) {
+            *alarm = Alarm::from_word(word);
+        }
+        self.chime = words[ALARM_SLOTS] == 1;
+    }
+}
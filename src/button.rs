@@ -6,6 +6,11 @@ use fugit::{ExtU64, TimerDurationU64, TimerInstantU64};
 
 #[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
 pub enum Event {
+    /// 按下的瞬间，不等待判断单击还是多击
+    Pressed,
+    /// 松开的瞬间（去抖之后），与 `Pressed` 成对出现。
+    /// 长按松开时在 `LongPressStop` 的下一个节拍发出，两个事件不会挤在同一次 `tick` 里
+    Released,
     Click,
     DoubleClick,
     MultiClick(u32),
@@ -34,6 +39,8 @@ enum State {
     Count = 3,
     Press = 6,
     Pressend = 7,
+    /// 已发出 `LongPressStop`，下一个节拍发出 `Released`
+    Pressup = 8,
 }
 
 impl<PIN, E, const TIMER_HZ: u32> Button<PIN, TIMER_HZ>
@@ -87,6 +94,9 @@ where
                     self.update_state(Down);
                     self.cnt_click = 0;
                     self.start_time = *time;
+                    if let Some(f) = self.attach_event_fn {
+                        f(Event::Pressed);
+                    }
                 }
             }
             Down => {
@@ -101,12 +111,18 @@ where
                 if !active && wait_time > self.debounce_ms {
                     self.cnt_click += 1;
                     self.update_state(Count);
+                    if let Some(f) = self.attach_event_fn {
+                        f(Event::Released);
+                    }
                 }
             }
             Count => {
                 if active {
                     self.update_state(Down);
                     self.start_time = *time;
+                    if let Some(f) = self.attach_event_fn {
+                        f(Event::Pressed);
+                    }
                 } else if wait_time > self.click_ms {
                     self.attach_event_fn.map(|f| {
                         f(match self.cnt_click {
@@ -129,8 +145,14 @@ where
             Pressend => {
                 if !active && wait_time > self.debounce_ms {
                     self.attach_event_fn.map(|f| f(Event::LongPressStop));
-                    self.reset();
+                    self.update_state(Pressup);
+                }
+            }
+            Pressup => {
+                if let Some(f) = self.attach_event_fn {
+                    f(Event::Released);
                 }
+                self.reset();
             }
         }
     }
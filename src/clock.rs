@@ -0,0 +1,116 @@
+use core::fmt;
+
+use defmt::Format;
+
+/// RTC 的低频时钟频率
+const LFCLK_HZ: u32 = 32_768;
+/// RTC 计数器只有 24 位
+const COUNTER_MASK: u32 = 0xFF_FFFF;
+const SECS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// 一天中的时刻
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Time {
+    pub fn new(hour: u8, minute: u8, second: u8) -> Option<Self> {
+        (hour < 24 && minute < 60 && second < 60).then_some(Self {
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// 解析 `hh:mm` 或 `hh:mm:ss`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(':');
+        let hour = parts.next()?.parse().ok()?;
+        let minute = parts.next()?.parse().ok()?;
+        let second = match parts.next() {
+            Some(second) => second.parse().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Self::new(hour, minute, second)
+    }
+
+    fn from_secs(secs: u32) -> Self {
+        Self {
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    fn secs(&self) -> u32 {
+        self.hour as u32 * 3600 + self.minute as u32 * 60 + self.second as u32
+    }
+
+    /// 从零点起的分钟数
+    pub fn minute_of_day(&self) -> u32 {
+        self.hour as u32 * 60 + self.minute as u32
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+/// 墙上时钟：按 RTC 计数器的增量累计低频时钟周期，精度取决于 32.768kHz 晶振。
+/// 断电或复位后需要重新设置
+pub struct WallClock {
+    /// RTC 每个计数的低频时钟周期数，即分频值加一
+    period: u32,
+    last_counter: u32,
+    /// 不足一秒的低频时钟周期
+    lf_ticks: u32,
+    secs: u32,
+    is_set: bool,
+}
+
+impl WallClock {
+    /// `prescaler` 为 RTC 的分频值
+    pub fn new(prescaler: u32) -> Self {
+        Self {
+            period: prescaler + 1,
+            last_counter: 0,
+            lf_ticks: 0,
+            secs: 0,
+            is_set: false,
+        }
+    }
+
+    /// 在 RTC 中断中调用，`counter` 为当前计数值，漏掉的节拍也会补上
+    pub fn tick(&mut self, counter: u32) {
+        let delta = counter.wrapping_sub(self.last_counter) & COUNTER_MASK;
+        self.last_counter = counter;
+        self.lf_ticks += delta * self.period;
+        while self.lf_ticks >= LFCLK_HZ {
+            self.lf_ticks -= LFCLK_HZ;
+            self.secs = (self.secs + 1) % SECS_PER_DAY;
+        }
+    }
+
+    pub fn set(&mut self, time: Time) {
+        self.secs = time.secs();
+        self.lf_ticks = 0;
+        self.is_set = true;
+    }
+
+    /// 是否设置过时间，没有设置时从 00:00:00 开始走
+    pub fn is_set(&self) -> bool {
+        self.is_set
+    }
+
+    pub fn now(&self) -> Time {
+        Time::from_secs(self.secs)
+    }
+}
@@ -0,0 +1,195 @@
+use defmt::Format;
+
+use crate::melody::Melody;
+use crate::rand::Rand;
+use crate::sample::Sample;
+
+/// 打击乐器，在蜂鸣器上用扫频和随机跳频合成，或者播放 PCM 采样
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drum {
+    Rest,
+    Kick,
+    Snare,
+    HiHat,
+    Click,
+    Sample(&'static Sample),
+}
+
+/// 音色参数：持续时间内频率从 `start_hz` 线性滑到 `end_hz`，
+/// `noise_hz` 不为 0 时每个 tick 在该范围内随机跳频
+#[derive(Clone, Copy)]
+struct Patch {
+    length_ms: u32,
+    start_hz: u32,
+    end_hz: u32,
+    noise_hz: u32,
+}
+
+impl Drum {
+    fn patch(&self) -> Option<Patch> {
+        let patch = match *self {
+            Drum::Rest | Drum::Sample(_) => return None,
+            Drum::Kick => Patch {
+                length_ms: 40,
+                start_hz: 160,
+                end_hz: 50,
+                noise_hz: 0,
+            },
+            Drum::Snare => Patch {
+                length_ms: 60,
+                start_hz: 1800,
+                end_hz: 900,
+                noise_hz: 2500,
+            },
+            Drum::HiHat => Patch {
+                length_ms: 20,
+                start_hz: 7000,
+                end_hz: 6000,
+                noise_hz: 4000,
+            },
+            Drum::Click => Patch {
+                length_ms: 4,
+                start_hz: 2000,
+                end_hz: 2000,
+                noise_hz: 0,
+            },
+        };
+        Some(patch)
+    }
+}
+
+/// 鼓声此刻对蜂鸣器的要求
+pub enum Sound {
+    /// 以该频率发声
+    Freq(u32),
+    /// 开始播放采样
+    Sample(&'static Sample),
+    /// 采样仍在播放，保持输出
+    Hold,
+}
+
+/// 一次正在发声的敲击
+pub struct Hit {
+    kind: Kind,
+    elapsed_ms: u32,
+}
+
+enum Kind {
+    Synth(Patch),
+    Sample(&'static Sample),
+}
+
+impl Hit {
+    pub fn new(drum: Drum) -> Option<Self> {
+        let kind = match drum {
+            Drum::Sample(sample) => Kind::Sample(sample),
+            drum => Kind::Synth(drum.patch()?),
+        };
+        Some(Self {
+            kind,
+            elapsed_ms: 0,
+        })
+    }
+
+    /// 前进 1ms，返回这一刻的声音，敲击结束时返回 `None`
+    pub fn next(&mut self, rand: &mut Rand) -> Option<Sound> {
+        let patch = match self.kind {
+            Kind::Synth(patch) => patch,
+            Kind::Sample(sample) => {
+                let elapsed_ms = self.elapsed_ms;
+                self.elapsed_ms += 1;
+                return match elapsed_ms {
+                    0 => Some(Sound::Sample(sample)),
+                    ms if ms < sample.duration_ms() => Some(Sound::Hold),
+                    _ => None,
+                };
+            }
+        };
+        let Patch {
+            length_ms,
+            start_hz,
+            end_hz,
+            noise_hz,
+        } = patch;
+
+        if self.elapsed_ms >= length_ms {
+            return None;
+        }
+
+        // 线性扫频
+        let sweep = if end_hz >= start_hz {
+            start_hz + (end_hz - start_hz) * self.elapsed_ms / length_ms
+        } else {
+            start_hz - (start_hz - end_hz) * self.elapsed_ms / length_ms
+        };
+        self.elapsed_ms += 1;
+
+        Some(Sound::Freq(sweep + rand.below(noise_hz)))
+    }
+}
+
+/// 鼓点轨道的播放进度，跟随旋律的曲子时间推进
+pub struct Track {
+    pos: usize,
+    at_ms: u32,
+    hit: Option<Hit>,
+}
+
+impl Track {
+    pub const fn new() -> Self {
+        Self {
+            pos: 0,
+            at_ms: 0,
+            hit: None,
+        }
+    }
+
+    pub fn is_sounding(&self) -> bool {
+        self.hit.is_some()
+    }
+
+    /// 定位到曲子时间 `ms` 之后的第一个鼓点
+    pub fn seek(&mut self, melody: &Melody, ms: u32) {
+        self.hit = None;
+        self.pos = 0;
+        self.at_ms = 0;
+
+        let cycle_ms = melody.drum_cycle_ms();
+        if cycle_ms == 0 {
+            return;
+        }
+
+        self.at_ms = ms - ms % cycle_ms;
+        while self.at_ms < ms {
+            self.advance(melody);
+        }
+    }
+
+    /// 推进到曲子时间 `now_ms`，返回此刻的鼓声，没有鼓声时返回 `None`
+    pub fn tick(&mut self, melody: &Melody, now_ms: u32, rand: &mut Rand) -> Option<Sound> {
+        if melody.drum_cycle_ms() > 0 {
+            while self.at_ms <= now_ms {
+                if let Some((drum, _)) = melody.drum(self.pos) {
+                    if let Some(hit) = Hit::new(drum) {
+                        self.hit = Some(hit);
+                    }
+                }
+                self.advance(melody);
+            }
+        }
+
+        let sound = self.hit.as_mut().and_then(|hit| hit.next(rand));
+        if sound.is_none() {
+            self.hit = None;
+        }
+        sound
+    }
+
+    #[inline]
+    fn advance(&mut self, melody: &Melody) {
+        if let Some((_, delay_ms)) = melody.drum(self.pos) {
+            self.at_ms += delay_ms.max(1);
+        }
+        self.pos = (self.pos + 1) % melody.drum_len();
+    }
+}
@@ -0,0 +1,86 @@
+use defmt::Format;
+
+use crate::tone::transpose;
+
+/// 单个音符上的音高效果
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    None,
+    /// 颤音：起音 `delay_ms` 后以 `rate_dhz` / 10 Hz 的速率在 ±`depth_cents` 音分内摆动
+    Vibrato {
+        rate_dhz: u16,
+        depth_cents: u16,
+        delay_ms: u16,
+    },
+    /// 弯音：从偏移 `cents` 音分处起音，`time_ms` 内回到原音高
+    Bend { cents: i16, time_ms: u16 },
+    /// 滑音：`time_ms` 内从上一个音符的音高滑到本音符
+    Portamento { time_ms: u16 },
+}
+
+impl Effect {
+    pub const VIBRATO: Effect = Effect::Vibrato {
+        rate_dhz: 55,
+        depth_cents: 20,
+        delay_ms: 250,
+    };
+
+    pub const BEND_UP: Effect = Effect::Bend {
+        cents: -100,
+        time_ms: 120,
+    };
+
+    pub const GLIDE: Effect = Effect::Portamento { time_ms: 80 };
+
+    /// 第 `ms` 毫秒的频率；`freq` 为本音符频率，`from_freq` 为上一个音符频率
+    pub fn freq_at(&self, freq: u32, from_freq: u32, ms: u32) -> u32 {
+        match *self {
+            Effect::None => freq,
+            Effect::Vibrato {
+                rate_dhz,
+                depth_cents,
+                delay_ms,
+            } => {
+                let Some(ms) = ms.checked_sub(delay_ms as u32) else {
+                    return freq;
+                };
+                // 三角波 LFO，phase 取值 0..4000 对应一个周期
+                let phase = (ms * rate_dhz as u32 * 4 / 10) % 4000;
+                let lfo = match phase {
+                    0..=999 => phase as i32,
+                    1000..=2999 => 2000 - phase as i32,
+                    _ => phase as i32 - 4000,
+                };
+                transpose(freq, lfo * depth_cents as i32 / 1000)
+            }
+            Effect::Bend { cents, time_ms } => {
+                let time_ms = time_ms as u32;
+                if ms >= time_ms {
+                    freq
+                } else {
+                    let left = (time_ms - ms) as i32;
+                    transpose(freq, cents as i32 * left / time_ms as i32)
+                }
+            }
+            Effect::Portamento { time_ms } => {
+                let time_ms = time_ms as u32;
+                if ms >= time_ms || from_freq == 0 {
+                    freq
+                } else if freq >= from_freq {
+                    from_freq + (freq - from_freq) * ms / time_ms
+                } else {
+                    from_freq - (from_freq - freq) * ms / time_ms
+                }
+            }
+        }
+    }
+
+    /// 第 `ms` 毫秒之后音高是否保持不变
+    pub fn settled_at(&self, ms: u32) -> bool {
+        match *self {
+            Effect::None => true,
+            Effect::Vibrato { .. } => false,
+            Effect::Bend { time_ms, .. } | Effect::Portamento { time_ms } => ms >= time_ms as u32,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+/// 5x5 点阵字形，每行 5 位，最高位为最左一列。
+/// 音名字母 A-G 占左边 4 列，最右一列留给升号等标记
+pub type Glyph = [u8; 5];
+
+/// 空格的宽度（列）
+const SPACE_WIDTH: u32 = 2;
+
+#[rustfmt::skip]
+const LETTERS: [Glyph; 26] = [
+    [0b01100, 0b10010, 0b11110, 0b10010, 0b10010], // A
+    [0b11100, 0b10010, 0b11100, 0b10010, 0b11100], // B
+    [0b01110, 0b10000, 0b10000, 0b10000, 0b01110], // C
+    [0b11100, 0b10010, 0b10010, 0b10010, 0b11100], // D
+    [0b11110, 0b10000, 0b11100, 0b10000, 0b11110], // E
+    [0b11110, 0b10000, 0b11100, 0b10000, 0b10000], // F
+    [0b01110, 0b10000, 0b10110, 0b10010, 0b01110], // G
+    [0b10010, 0b10010, 0b11110, 0b10010, 0b10010], // H
+    [0b11100, 0b01000, 0b01000, 0b01000, 0b11100], // I
+    [0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // J
+    [0b10010, 0b10100, 0b11000, 0b10100, 0b10010], // K
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b11110], // L
+    [0b10001, 0b11011, 0b10101, 0b10001, 0b10001], // M
+    [0b10001, 0b11001, 0b10101, 0b10011, 0b10001], // N
+    [0b01100, 0b10010, 0b10010, 0b10010, 0b01100], // O
+    [0b11100, 0b10010, 0b11100, 0b10000, 0b10000], // P
+    [0b01100, 0b10010, 0b10010, 0b10110, 0b01110], // Q
+    [0b11100, 0b10010, 0b11100, 0b10100, 0b10010], // R
+    [0b01110, 0b10000, 0b01100, 0b00010, 0b11100], // S
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100], // T
+    [0b10010, 0b10010, 0b10010, 0b10010, 0b01100], // U
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // V
+    [0b10001, 0b10001, 0b10101, 0b11011, 0b10001], // W
+    [0b10001, 0b01010, 0b00100, 0b01010, 0b10001], // X
+    [0b10001, 0b01010, 0b00100, 0b00100, 0b00100], // Y
+    [0b11110, 0b00010, 0b00100, 0b01000, 0b11110], // Z
+];
+
+#[rustfmt::skip]
+const DIGITS: [Glyph; 10] = [
+    [0b01110, 0b01010, 0b01010, 0b01010, 0b01110], // 0
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b01110], // 1
+    [0b01110, 0b00010, 0b01110, 0b01000, 0b01110], // 2
+    [0b01110, 0b00010, 0b00110, 0b00010, 0b01110], // 3
+    [0b01010, 0b01010, 0b01110, 0b00010, 0b00010], // 4
+    [0b01110, 0b01000, 0b01110, 0b00010, 0b01110], // 5
+    [0b01110, 0b01000, 0b01110, 0b01010, 0b01110], // 6
+    [0b01110, 0b00010, 0b00100, 0b00100, 0b00100], // 7
+    [0b01110, 0b01010, 0b01110, 0b01010, 0b01110], // 8
+    [0b01110, 0b01010, 0b01110, 0b00010, 0b01110], // 9
+];
+
+#[rustfmt::skip]
+const SYMBOLS: [(char, Glyph); 7] = [
+    ('-', [0b00000, 0b00000, 0b11100, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b10000]),
+    (':', [0b00000, 0b10000, 0b00000, 0b10000, 0b00000]),
+    ('!', [0b10000, 0b10000, 0b10000, 0b00000, 0b10000]),
+    ('?', [0b11000, 0b00100, 0b01000, 0b00000, 0b01000]),
+    ('\'', [0b10000, 0b10000, 0b00000, 0b00000, 0b00000]),
+    ('/', [0b00010, 0b00100, 0b00100, 0b01000, 0b01000]),
+];
+
+/// 字符的字形，小写字母按大写显示，没有字形的字符为空
+pub fn glyph(c: char) -> Option<Glyph> {
+    match c.to_ascii_uppercase() {
+        c @ 'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+        c @ '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        c => SYMBOLS
+            .iter()
+            .find(|&&(symbol, _)| symbol == c)
+            .map(|&(_, glyph)| glyph),
+    }
+}
+
+/// 字形实际占用的列：最左一列的位置和列数，空白字形按空格算
+pub fn span(glyph: Option<Glyph>) -> (u32, u32) {
+    let bits = glyph.map_or(0, |glyph| glyph.iter().fold(0, |bits, &row| bits | row));
+    if bits == 0 {
+        return (0, SPACE_WIDTH);
+    }
+    let start = bits.leading_zeros() - 3;
+    let end = 5 - bits.trailing_zeros();
+    (start, end - start)
+}
@@ -0,0 +1,71 @@
+use defmt::Format;
+
+/// ADSR 音量包络，电平取值 0-100
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Envelope {
+    pub attack_ms: u16,
+    pub decay_ms: u16,
+    pub sustain: u8,
+    pub release_ms: u16,
+}
+
+impl Envelope {
+    /// 按下后第 `ms` 毫秒的电平
+    pub fn level(&self, ms: u32) -> u32 {
+        let attack_ms = self.attack_ms as u32;
+        let decay_ms = self.decay_ms as u32;
+        let sustain = self.sustain.min(100) as u32;
+
+        if ms < attack_ms {
+            100 * ms / attack_ms
+        } else if ms < attack_ms + decay_ms {
+            100 - (100 - sustain) * (ms - attack_ms) / decay_ms
+        } else {
+            sustain
+        }
+    }
+
+    /// 松开后第 `ms` 毫秒的电平，`from` 为松开时的电平
+    pub fn release_level(&self, from: u32, ms: u32) -> u32 {
+        let release_ms = self.release_ms as u32;
+        if ms >= release_ms {
+            0
+        } else {
+            from - from * ms / release_ms
+        }
+    }
+
+    /// 起音加衰减的总时长，之后保持在延音电平
+    pub fn onset_ms(&self) -> u32 {
+        self.attack_ms as u32 + self.decay_ms as u32
+    }
+}
+
+/// 音色预设，每首曲子选择一种
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instrument {
+    /// 无包络的方波，音符开关都是硬切
+    Square,
+    Organ,
+    Piano,
+    Pluck,
+    Pad,
+}
+
+impl Instrument {
+    pub const fn envelope(&self) -> Envelope {
+        let (attack_ms, decay_ms, sustain, release_ms) = match *self {
+            Instrument::Square => (0, 0, 100, 0),
+            Instrument::Organ => (5, 0, 100, 20),
+            Instrument::Piano => (2, 300, 40, 80),
+            Instrument::Pluck => (1, 120, 0, 30),
+            Instrument::Pad => (120, 200, 70, 250),
+        };
+        Envelope {
+            attack_ms,
+            decay_ms,
+            sustain,
+            release_ms,
+        }
+    }
+}
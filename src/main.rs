@@ -12,10 +12,32 @@ const TIMER_HZ: u32 = 1_000_000; // 1MHz
 nrf_timer0_monotonic!(Mono, TIMER_HZ);
 
 mod accel;
+mod alarm;
 mod button;
+mod clock;
+mod drum;
+mod effect;
+mod font;
+mod instrument;
 mod melody;
+mod metronome;
+mod mode;
 mod player;
+mod rand;
+mod recorder;
+mod sample;
+mod scroll;
+mod serial;
+mod settings;
+mod sleep;
+mod storage;
 mod tone;
+mod tuner;
+mod ui;
+mod visualizer;
+mod voice;
+
+use musicbox_core::{theremin, volume, wavetable};
 
 #[rtic::app(device = bsp::pac, peripherals = true, dispatchers = [SWI0_EGU0])]
 mod app {
@@ -24,18 +46,32 @@ mod app {
     use bsp::hal::clocks::Clocks;
     use bsp::hal::delay::Delay;
     use bsp::hal::gpio::{Input, Pin, PullUp};
+    use bsp::hal::rng::Rng;
     use bsp::hal::rtc::{Rtc, RtcInterrupt};
     use bsp::hal::twim;
+    use bsp::hal::uarte;
     use bsp::pac::twim0::frequency::FREQUENCY_A;
-    use bsp::pac::{PWM1, RTC0, TIMER1, TIMER2, TWIM0};
+    use bsp::pac::{POWER, PWM1, RTC0, TIMER1, TIMER2, TWIM0};
     use bsp::Board;
 
+    use heapless::mpmc::Q16;
     use lsm303agr::{AccelMode, AccelOutputDataRate, Lsm303agr};
 
     type Accel = accel::Accel<twim::Twim<TWIM0>, TIMER_HZ>;
+    type AlarmClock = alarm::AlarmClock;
     type Button = button::Button<Pin<Input<PullUp>>, TIMER_HZ>;
     type Display = bsp::display::nonblocking::Display<TIMER1>;
+    type Metronome = metronome::Metronome;
     type Player = player::Player<'static, TIMER2, PWM1>;
+    type Recorder = recorder::Recorder<TIMER_HZ>;
+    type Serial = serial::Serial;
+    type SleepTimer = sleep::SleepTimer<TIMER_HZ>;
+    type Storage = storage::Storage;
+    type Theremin = theremin::Theremin;
+    type TuningFork = tuner::TuningFork;
+    type TapTempo = metronome::TapTempo<TIMER_HZ>;
+    type Ui = ui::Ui<TIMER_HZ>;
+    type WallClock = clock::WallClock;
 
     const MELODY_LIST: &[melody::Melody] = &[
         melody::SUPER_MARIOBROS,
@@ -44,21 +80,61 @@ mod app {
         melody::HAPPY_BIRTHDAY,
     ];
 
+    /// 播放器事件队列：TIMER2 中断里只入队，由 `handle_player_queue` 取出处理
+    static PLAYER_EVENTS: Q16<player::Event> = Q16::new();
+
+    /// RTC0 的分频值，32_768 / (327 + 1) 约 100Hz
+    const RTC_PRESCALER: u32 = 327;
+
+    /// 闹钟从静音渐强到当前音量的时长
+    const ALARM_RAMP_MS: u32 = 30_000;
+
+    /// 节拍器每一拍在点阵上亮的时长
+    const BEAT_FLASH_MS: u64 = 100;
+
+    /// 睡眠定时到点后淡出的时长
+    const SLEEP_FADE_MS: u32 = 10_000;
+
+    /// 设置改动后停稳这么久才写入 flash
+    const SETTINGS_SETTLE_MS: u64 = 3_000;
+
+    /// 长按快进快退时每隔多少个按键节拍（10ms）跳一小节，每段能听到 150ms
+    const SCAN_TICKS: u32 = 15;
+
     #[shared]
     struct Shared {
         accel: Accel,
+        alarm: AlarmClock,
+        /// 最近一次读到的姿态
+        attitude: accel::Attitude,
         btn1: Button,
         btn2: Button,
+        clock: WallClock,
         display: Display,
+        fork: TuningFork,
+        /// 节拍器的设置，停下后保留，下次打开接着用
+        metronome: Metronome,
+        mode: mode::Mode,
         player: Player,
+        /// 内置曲子加上最近一段录音
+        playlist: recorder::Playlist,
+        recorder: Recorder,
+        serial: Serial,
+        sleep: SleepTimer,
+        storage: Storage,
+        tap: TapTempo,
+        theremin: Theremin,
+        ui: Ui,
     }
 
     #[local]
     struct Local {
         rtc0: Rtc<RTC0>,
+        power: POWER,
     }
 
-    #[init]
+    /// 录音的缓冲由 `Playlist` 独占
+    #[init(local = [takes: recorder::Takes = recorder::Takes::new()])]
     fn init(ctx: init::Context) -> (Shared, Local) {
         defmt::info!("init musicbox");
 
@@ -73,7 +149,7 @@ mod app {
 
         // RTC at 100Hz (32_768 / (327 + 1))
         // 100Hz; 10ms period
-        let mut rtc0 = Rtc::new(board.RTC0, 327).unwrap();
+        let mut rtc0 = Rtc::new(board.RTC0, RTC_PRESCALER).unwrap();
         rtc0.enable_event(RtcInterrupt::Tick);
         rtc0.enable_interrupt(RtcInterrupt::Tick, None);
         rtc0.enable_counter();
@@ -93,6 +169,13 @@ mod app {
                 defmt::debug!("shake event");
                 handle_shake_event::spawn().ok();
             });
+            accel.attach_tilt_event(|tilt| {
+                defmt::debug!("tilt event: {}", tilt);
+                handle_tilt_event::spawn(tilt).ok();
+            });
+            accel.attach_attitude_event(|attitude| {
+                handle_attitude_event::spawn(attitude).ok();
+            });
 
             accel
         };
@@ -103,7 +186,9 @@ mod app {
             let mut btn = Button::new(pin);
             btn.attach_event(|event| {
                 defmt::debug!("button A event: {:?}", &event);
-                handle_btn1_event::spawn(event).ok();
+                if let Err(event) = handle_btn1_event::spawn(event) {
+                    defmt::warn!("button A event dropped: {:?}", event);
+                }
             });
             btn
         };
@@ -114,7 +199,9 @@ mod app {
             let mut btn = Button::new(pin);
             btn.attach_event(|event| {
                 defmt::debug!("button B event: {:?}", &event);
-                handle_btn2_event::spawn(event).ok();
+                if let Err(event) = handle_btn2_event::spawn(event) {
+                    defmt::warn!("button B event dropped: {:?}", event);
+                }
             });
             btn
         };
@@ -126,88 +213,817 @@ mod app {
         };
 
         // Player
-        let player = {
+        let mut player = {
             let pin = board
                 .speaker_pin
                 .into_push_pull_output(bsp::hal::gpio::Level::High)
                 .degrade();
-            Player::new(board.TIMER2, board.PWM1, pin, MELODY_LIST)
+            let mut player = Player::new(board.TIMER2, board.PWM1, pin, MELODY_LIST);
+            player.set_seed(Rng::new(board.RNG).random_u32());
+            player.attach_event(|event| {
+                if PLAYER_EVENTS.enqueue(event).is_err() {
+                    defmt::warn!("player event dropped: {:?}", event);
+                }
+                // 挂起中断而不是 spawn：处理任务正在收尾时再挂起一次也不会丢
+                rtic::pend(bsp::pac::Interrupt::SWI1_EGU1);
+            });
+            player
         };
 
+        // Serial commands over the debugger USB
+        let serial = {
+            let pins: uarte::Pins = board.uart.into();
+            let mut serial = Serial::new(board.UART0, pins);
+            serial.attach_command(|command| {
+                defmt::debug!("serial command: {:?}", command);
+                handle_serial_command::spawn(command).ok();
+            });
+            serial
+        };
+
+        // Alarms and settings saved in flash
+        let storage = Storage::new(board.NVMC);
+        let mut alarm = AlarmClock::new();
+        if let Some(words) = storage.load(storage::Key::Alarms) {
+            alarm.load(&words);
+        }
+        if let Some(calibration) = storage
+            .load(storage::Key::Calibration)
+            .and_then(|words| volume::Calibration::from_words(&words))
+        {
+            player.set_calibration(calibration);
+        }
+        let mut metronome = Metronome::new(120, metronome::Signature { beats: 4, unit: 4 });
+        let mut fork = TuningFork::new();
+        if let Some(settings) = storage
+            .load(storage::Key::Settings)
+            .and_then(|words| settings::Settings::from_words(&words))
+        {
+            defmt::info!("restore {}", settings);
+            restore_settings(&settings, &mut player, &mut metronome, &mut fork);
+        }
+
+        sleep_watch::spawn().ok();
+        alarm_watch::spawn().ok();
+        settings_watch::spawn().ok();
+
         (
             Shared {
                 accel,
+                alarm,
+                attitude: accel::Attitude { x_mg: 0, y_mg: 0 },
                 btn1,
                 btn2,
+                clock: WallClock::new(RTC_PRESCALER),
                 display,
+                fork,
+                metronome,
+                mode: mode::Mode::Player,
                 player,
+                playlist: recorder::Playlist::new(MELODY_LIST, ctx.local.takes),
+                recorder: Recorder::new(),
+                serial,
+                sleep: SleepTimer::new(),
+                storage,
+                tap: TapTempo::new(),
+                theremin: Theremin::new(),
+                ui: Ui::new(),
+            },
+            Local {
+                rtc0,
+                power: board.POWER,
             },
-            Local { rtc0 },
         )
     }
 
-    #[task(binds = RTC0, local = [rtc0], shared = [accel, btn1, btn2])]
+    #[task(binds = RTC0, local = [rtc0], shared = [accel, btn1, btn2, clock, display, ui])]
     fn rtc0(mut ctx: rtc0::Context) {
         let now = Mono::now();
         ctx.local.rtc0.reset_event(RtcInterrupt::Tick);
+        let counter = ctx.local.rtc0.get_counter();
+        ctx.shared.clock.lock(|clock| clock.tick(counter));
         ctx.shared.accel.lock(|accel| accel.tick(&now));
         ctx.shared.btn1.lock(|btn| btn.tick(&now));
         ctx.shared.btn2.lock(|btn| btn.tick(&now));
+        (ctx.shared.display, ctx.shared.ui).lock(|display, ui| ui.tick(display, &now));
+    }
+
+    /// 睡眠定时：到点后淡出停止，播完本曲模式下等播放器把当前曲子播完停下
+    /// （暂停、停止或切换模式都不算），然后进入低功耗。
+    /// 设了闹钟或整点报时就不关机，否则到点不会响。关机前保存淡出前的播放位置
+    #[task(
+        priority = 1,
+        local = [power],
+        shared = [alarm, fork, metronome, player, sleep, storage]
+    )]
+    async fn sleep_watch(mut ctx: sleep_watch::Context) {
+        loop {
+            Mono::delay(1.secs()).await;
+            let now = Mono::now();
+            let (expired, setting) = ctx
+                .shared
+                .sleep
+                .lock(|sleep| (sleep.expired(&now), sleep.setting()));
+
+            let settings = (
+                &mut ctx.shared.fork,
+                &mut ctx.shared.metronome,
+                &mut ctx.shared.player,
+            )
+                .lock(|fork, metronome, ply| snapshot_settings(ply, metronome, fork));
+            if expired {
+                defmt::info!("sleep timer expired");
+                ctx.shared
+                    .player
+                    .lock(|ply| ply.stop_with_fade(SLEEP_FADE_MS).ok());
+                Mono::delay((SLEEP_FADE_MS as u64 + 500).millis()).await;
+            } else if setting == sleep::Sleep::EndOfSong
+                && ctx.shared.player.lock(|ply| !ply.stops_after_song())
+            {
+                defmt::info!("song finished, going to sleep");
+                ctx.shared
+                    .sleep
+                    .lock(|sleep| sleep.set(sleep::Sleep::Off, &now));
+            } else {
+                continue;
+            }
+
+            if ctx.shared.alarm.lock(|alarm| alarm.is_armed()) {
+                defmt::info!("alarm armed, staying on");
+                continue;
+            }
+            ctx.shared
+                .storage
+                .lock(|storage| storage.save(storage::Key::Settings, &settings.to_words()));
+            sleep::system_off(ctx.local.power);
+        }
+    }
+
+    /// 保存设置：改动后停稳 `SETTINGS_SETTLE_MS` 再写，按住音量键不会反复擦写 flash。
+    /// 播放时一律不写，暂停或停止后连同实际位置一起保存，关机前由睡眠定时保存，
+    /// 所以整理页面时的擦除不会让播放停顿
+    #[task(priority = 1, shared = [fork, metronome, player, storage])]
+    async fn settings_watch(mut ctx: settings_watch::Context) {
+        let mut snapshot = || {
+            (
+                &mut ctx.shared.fork,
+                &mut ctx.shared.metronome,
+                &mut ctx.shared.player,
+            )
+                .lock(|fork, metronome, ply| {
+                    (snapshot_settings(ply, metronome, fork), ply.is_playing())
+                })
+        };
+        let mut saved = snapshot().0;
+        let mut last = saved;
+        let mut changed_at = Mono::now();
+        loop {
+            Mono::delay(1.secs()).await;
+            let now = Mono::now();
+            let (settings, playing) = snapshot();
+            if playing || settings == saved {
+                continue;
+            }
+            if settings != last {
+                last = settings;
+                changed_at = now;
+            }
+            if (now - changed_at).to_millis() < SETTINGS_SETTLE_MS {
+                continue;
+            }
+            ctx.shared
+                .storage
+                .lock(|storage| storage.save(storage::Key::Settings, &settings.to_words()));
+            saved = settings;
+            defmt::debug!("settings saved {}", settings);
+        }
+    }
+
+    /// 闹钟和整点报时：每秒对一次墙上时钟，时间没有设置过时不响
+    #[task(priority = 1, shared = [alarm, clock, mode, player, display, ui])]
+    async fn alarm_watch(mut ctx: alarm_watch::Context) {
+        loop {
+            Mono::delay(1.secs()).await;
+            let Some(now) = ctx
+                .shared
+                .clock
+                .lock(|clock| clock.is_set().then(|| clock.now()))
+            else {
+                continue;
+            };
+            let Some(action) = ctx.shared.alarm.lock(|alarm| alarm.tick(now)) else {
+                continue;
+            };
+
+            defmt::info!("{} {}", now.minute_of_day(), action);
+            // 闹钟切回播放器模式；整点报时不打断节拍器和音叉
+            let mode = ctx.shared.mode.lock(|mode| {
+                if let alarm::Action::Ring(_) = action {
+                    *mode = mode::Mode::Player;
+                }
+                *mode
+            });
+            if let alarm::Action::Ring(_) = action {
+                (&mut ctx.shared.display, &mut ctx.shared.ui)
+                    .lock(|display, ui| ui.clear_pages(display));
+            }
+            let result = ctx.shared.player.lock(|ply| match action {
+                alarm::Action::Ring(song) => {
+                    if ply.is_muted() {
+                        ply.toggle_mute();
+                    }
+                    ply.play_song(song, ALARM_RAMP_MS)
+                }
+                alarm::Action::Chime(_) if mode == mode::Mode::Player => {
+                    ply.play_jingle(&melody::CHIME)
+                }
+                alarm::Action::Chime(_) => Ok(()),
+                alarm::Action::Silence => ply.stop(),
+            });
+            if let Err(err) = result {
+                defmt::warn!("alarm: {}", err);
+            }
+        }
     }
 
-    #[task(priority = 1, shared = [player])]
+    /// 停止响铃：`snooze` 为真时贪睡，过几分钟再响，否则关掉
+    #[task(priority = 1, shared = [alarm, clock, player, display, ui])]
+    async fn stop_alarm(mut ctx: stop_alarm::Context, snooze: bool) {
+        let now = ctx.shared.clock.lock(|clock| clock.now());
+        ctx.shared.alarm.lock(|alarm| {
+            if snooze {
+                alarm.snooze(now);
+            } else {
+                alarm.dismiss();
+            }
+        });
+        ctx.shared.player.lock(|ply| ply.stop().ok());
+
+        let image = if snooze {
+            ui::dial(alarm::SNOOZE_MINUTES)
+        } else {
+            ui::sleep(sleep::Sleep::Off)
+        };
+        let now = Mono::now();
+        (ctx.shared.display, ctx.shared.ui).lock(|display, ui| ui.toast(display, &image, &now));
+        defmt::info!("alarm {}", if snooze { "snoozed" } else { "dismissed" });
+    }
+
+    #[task(priority = 1, shared = [alarm, fork, metronome, mode, player])]
     async fn handle_shake_event(mut ctx: handle_shake_event::Context) {
+        if ctx.shared.alarm.lock(|alarm| alarm.is_ringing()) {
+            stop_alarm::spawn(true).ok();
+            return;
+        }
+        match ctx.shared.mode.lock(|mode| *mode) {
+            mode::Mode::Metronome => {
+                (ctx.shared.metronome, ctx.shared.player).lock(|settings, ply| {
+                    if ply.metronome().is_some() {
+                        ply.stop_metronome();
+                    } else {
+                        ply.start_metronome(*settings);
+                    }
+                    defmt::info!("metronome running {}", ply.metronome().is_some());
+                });
+                return;
+            }
+            mode::Mode::Tuner => {
+                (ctx.shared.fork, ctx.shared.player).lock(|fork, ply| {
+                    if ply.pitch().is_some() {
+                        ply.stop_pitch();
+                    } else {
+                        ply.hold_pitch(fork.freq(), 100);
+                    }
+                    defmt::info!("tuner sounding {}", ply.pitch().is_some());
+                });
+                return;
+            }
+            // 倾斜乐器和录音由按键 B 控制发声
+            mode::Mode::Theremin | mode::Mode::Recorder => return,
+            mode::Mode::Player => {}
+        }
         ctx.shared.player.lock(|ply| {
-            if ply.is_playing() {
+            let result = if ply.is_playing() {
                 defmt::info!("music paused");
-                ply.pause();
+                ply.pause()
             } else {
                 defmt::info!("music playing");
-                ply.play_or_resume();
+                ply.play_or_resume()
+            };
+            if let Err(err) = result {
+                defmt::warn!("shake: {}", err);
             }
+            defmt::debug!("{:?}", ply.status());
         });
     }
 
-    #[task(priority = 1, shared = [player])]
+    #[task(
+        priority = 1,
+        local = [scan_ticks: u32 = 0],
+        shared = [
+            alarm, fork, metronome, mode, player, playlist, recorder, sleep, display, tap,
+            theremin, ui
+        ]
+    )]
     async fn handle_btn1_event(mut ctx: handle_btn1_event::Context, event: button::Event) {
         use button::Event::*;
 
-        ctx.shared.player.lock(|ply| match event {
+        // 响铃时单击贪睡，双击关掉
+        if matches!(event, Click | DoubleClick) && ctx.shared.alarm.lock(|alarm| alarm.is_ringing())
+        {
+            stop_alarm::spawn(event == Click).ok();
+            return;
+        }
+
+        if event == MultiClick(5) {
+            let now = Mono::now();
+            let setting = ctx.shared.sleep.lock(|sleep| {
+                let setting = sleep.setting().next();
+                sleep.set(setting, &now);
+                setting
+            });
+            ctx.shared
+                .player
+                .lock(|ply| ply.set_stop_after_song(setting == sleep::Sleep::EndOfSong));
+            (ctx.shared.display, ctx.shared.ui)
+                .lock(|display, ui| ui.toast(display, &ui::sleep(setting), &now));
+            defmt::info!("sleep timer {}", setting);
+            return;
+        }
+
+        if event == MultiClick(6) {
+            let mode = ctx.shared.mode.lock(|mode| {
+                *mode = mode.next();
+                *mode
+            });
+            let fork = ctx.shared.fork.lock(|fork| *fork);
+            let status = (ctx.shared.metronome, ctx.shared.player).lock(|settings, ply| {
+                match mode {
+                    mode::Mode::Metronome => {
+                        ply.stop().ok();
+                        ply.start_metronome(*settings);
+                    }
+                    mode::Mode::Tuner => ply.hold_pitch(fork.freq(), 100),
+                    mode::Mode::Theremin | mode::Mode::Recorder | mode::Mode::Player => {
+                        ply.stop_pitch()
+                    }
+                }
+                ply.status()
+            });
+            ctx.shared
+                .theremin
+                .lock(|theremin| theremin.set_gate(false));
+            let note = ctx.shared.recorder.lock(|recorder| {
+                recorder.stop();
+                recorder.name()
+            });
+            let now = Mono::now();
+            (ctx.shared.display, ctx.shared.ui).lock(|display, ui| {
+                match mode {
+                    mode::Mode::Player => show_status(display, ui, &status, &now),
+                    mode::Mode::Tuner => ui.alternate(display, ui::note(fork.name()), &now),
+                    mode::Mode::Recorder => ui.alternate(display, ui::note(note), &now),
+                    _ => ui.clear_pages(display),
+                }
+                ui.toast(display, &ui::mode(mode), &now);
+            });
+            defmt::info!("mode {}", mode);
+            return;
+        }
+
+        let mode = ctx.shared.mode.lock(|mode| *mode);
+        if mode == mode::Mode::Tuner {
+            // 音叉：单击降半音，双击标准音降 1Hz，长按降八度
+            match event {
+                Click => tune::spawn(-1, 0).ok(),
+                DoubleClick => tune::spawn(0, -1).ok(),
+                LongPressStart => tune::spawn(-12, 0).ok(),
+                _ => None,
+            };
+            return;
+        }
+        if mode == mode::Mode::Theremin {
+            // 倾斜乐器：单击切换音阶
+            if event == Click {
+                let scale = ctx.shared.theremin.lock(|theremin| {
+                    theremin.set_scale(theremin.scale().next());
+                    theremin.scale()
+                });
+                let now = Mono::now();
+                (ctx.shared.display, ctx.shared.ui)
+                    .lock(|display, ui| ui.toast(display, &ui::scale(scale), &now));
+                defmt::info!("theremin scale {}", scale);
+            }
+            return;
+        }
+
+        if mode == mode::Mode::Recorder {
+            // 录音：单击开始，再单击结束并播放，双击放弃
+            match event {
+                Click => {
+                    let now = Mono::now();
+                    let bpm = ctx.shared.metronome.lock(|settings| settings.bpm());
+                    let notes = ctx.shared.recorder.lock(|recorder| {
+                        if recorder.is_recording() {
+                            Some(recorder.finish(&now, bpm))
+                        } else {
+                            recorder.start();
+                            None
+                        }
+                    });
+                    match notes {
+                        Some(notes) if !notes.is_empty() => play_recording::spawn(notes, bpm).ok(),
+                        Some(_) => {
+                            (ctx.shared.display, ctx.shared.ui)
+                                .lock(|display, ui| ui.toast(display, &ui::discarded(), &now));
+                            defmt::info!("recording empty");
+                            None
+                        }
+                        None => {
+                            (ctx.shared.display, ctx.shared.ui).lock(|display, ui| {
+                                ui.toast(display, &ui::mode(mode::Mode::Recorder), &now)
+                            });
+                            defmt::info!("recording at {} bpm", bpm);
+                            None
+                        }
+                    };
+                }
+                DoubleClick => {
+                    ctx.shared.recorder.lock(|recorder| recorder.stop());
+                    let now = Mono::now();
+                    (ctx.shared.display, ctx.shared.ui)
+                        .lock(|display, ui| ui.toast(display, &ui::discarded(), &now));
+                    defmt::info!("recording discarded");
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        let scan_ticks = ctx.local.scan_ticks;
+        if mode == mode::Mode::Metronome {
+            // 节拍器：单击敲击测速（按按下的时刻计），长按减慢
+            let bpm = match event {
+                Pressed => {
+                    let now = Mono::now();
+                    ctx.shared.tap.lock(|tap| tap.press(&now));
+                    None
+                }
+                Click => ctx.shared.tap.lock(|tap| tap.commit()),
+                DoubleClick | MultiClick(_) => {
+                    ctx.shared.tap.lock(|tap| tap.discard());
+                    None
+                }
+                LongPressStart => {
+                    ctx.shared.tap.lock(|tap| tap.discard());
+                    *scan_ticks = 0;
+                    None
+                }
+                LongPressDuring => {
+                    *scan_ticks += 1;
+                    (*scan_ticks == SCAN_TICKS).then(|| {
+                        *scan_ticks = 0;
+                        ctx.shared.metronome.lock(|settings| settings.bpm() - 1)
+                    })
+                }
+                _ => None,
+            };
+            if let Some(bpm) = bpm {
+                (ctx.shared.metronome, ctx.shared.player).lock(|settings, ply| {
+                    adjust_metronome(settings, ply, |metronome| metronome.set_bpm(bpm));
+                });
+                defmt::info!("metronome {} bpm", bpm);
+            }
+            return;
+        }
+
+        let result = ctx.shared.player.lock(|ply| match event {
             Click => {
                 defmt::info!("volume - 10");
                 ply.volume_sub(10);
+                Ok(())
+            }
+            LongPressStart => {
+                defmt::info!("rewind");
+                *scan_ticks = 0;
+                ply.seek_bars(-1)
             }
-            LongPressStart | LongPressDuring | LongPressStop => {
-                defmt::info!("volume - 1");
-                ply.volume_sub(1);
+            LongPressDuring => {
+                *scan_ticks += 1;
+                if *scan_ticks == SCAN_TICKS {
+                    *scan_ticks = 0;
+                    ply.seek_bars(-1)
+                } else {
+                    Ok(())
+                }
             }
             DoubleClick => {
                 defmt::info!("prev music");
-                ply.prev();
+                ply.prev()
             }
-            _ => {}
-        })
+            MultiClick(3) => {
+                ply.next_speed();
+                defmt::info!("speed {}%", ply.speed());
+                Ok(())
+            }
+            MultiClick(4) => {
+                ply.toggle_mute();
+                defmt::info!("muted {}", ply.is_muted());
+                Ok(())
+            }
+            _ => Ok(()),
+        });
+        if let Err(err) = result {
+            defmt::warn!("button A: {}", err);
+        }
     }
 
-    #[task(priority = 1, shared = [player])]
+    #[task(
+        priority = 1,
+        local = [scan_ticks: u32 = 0],
+        shared = [alarm, attitude, metronome, mode, player, recorder, theremin, display, ui]
+    )]
     async fn handle_btn2_event(mut ctx: handle_btn2_event::Context, event: button::Event) {
         use button::Event::*;
 
-        ctx.shared.player.lock(|ply| match event {
+        if matches!(event, Click | DoubleClick) && ctx.shared.alarm.lock(|alarm| alarm.is_ringing())
+        {
+            stop_alarm::spawn(event == Click).ok();
+            return;
+        }
+
+        let mode = ctx.shared.mode.lock(|mode| *mode);
+        if mode == mode::Mode::Tuner {
+            // 音叉：单击升半音，双击标准音升 1Hz，长按升八度
+            match event {
+                Click => tune::spawn(1, 0).ok(),
+                DoubleClick => tune::spawn(0, 1).ok(),
+                LongPressStart => tune::spawn(12, 0).ok(),
+                _ => None,
+            };
+            return;
+        }
+        if mode == mode::Mode::Theremin {
+            // 倾斜乐器：按住发声，松开停止。长按松开时 `LongPressStop` 也算松开，
+            // 其中一个事件丢了也不会一直响
+            if matches!(event, Pressed | Released | LongPressStop) {
+                let gate = event == Pressed;
+                let attitude = ctx.shared.attitude.lock(|attitude| *attitude);
+                (ctx.shared.theremin, ctx.shared.player).lock(|theremin, ply| {
+                    theremin.set_gate(gate);
+                    play_theremin(theremin, attitude, ply);
+                });
+            }
+            return;
+        }
+        if mode == mode::Mode::Recorder {
+            // 录音：按住发出选中的音，录音时记下按住和松开的时长。
+            // 长按松开时 `LongPressStop` 先到，按它记下松开，随后的 `Released` 不再重复
+            let now = Mono::now();
+            (ctx.shared.recorder, ctx.shared.player).lock(|recorder, ply| match event {
+                Pressed => {
+                    let tone = recorder.press(&now);
+                    ply.hold_pitch(tone.freq() * voice::FREQ_SCALE, 100);
+                }
+                Released | LongPressStop => {
+                    recorder.release(&now);
+                    ply.stop_pitch();
+                }
+                _ => {}
+            });
+            return;
+        }
+
+        let scan_ticks = ctx.local.scan_ticks;
+        if mode == mode::Mode::Metronome {
+            // 节拍器：单击切换拍号，长按加快
+            (ctx.shared.metronome, ctx.shared.player).lock(|settings, ply| match event {
+                Click => {
+                    let signature = settings.signature().next();
+                    adjust_metronome(settings, ply, |metronome| {
+                        metronome.set_signature(signature)
+                    });
+                    defmt::info!("metronome {}/{}", signature.beats, signature.unit);
+                }
+                LongPressStart => *scan_ticks = 0,
+                LongPressDuring => {
+                    *scan_ticks += 1;
+                    if *scan_ticks == SCAN_TICKS {
+                        *scan_ticks = 0;
+                        let bpm = settings.bpm() + 1;
+                        adjust_metronome(settings, ply, |metronome| metronome.set_bpm(bpm));
+                        defmt::info!("metronome {} bpm", settings.bpm());
+                    }
+                }
+                _ => {}
+            });
+            return;
+        }
+
+        if event == MultiClick(6) {
+            // 切换音乐可视化的样式，滚动显示样式名
+            let now = Mono::now();
+            (ctx.shared.display, ctx.shared.ui).lock(|display, ui| {
+                let style = ui.visual_style().next();
+                ui.set_visual_style(display, style);
+                ui.scroll(display, style.name(), &now);
+                defmt::info!("visualizer {}", style);
+            });
+            return;
+        }
+
+        let result = ctx.shared.player.lock(|ply| match event {
             Click => {
                 defmt::info!("volume + 10");
                 ply.volume_add(10);
+                Ok(())
+            }
+            LongPressStart => {
+                defmt::info!("fast forward");
+                *scan_ticks = 0;
+                ply.seek_bars(1)
             }
-            LongPressStart | LongPressDuring | LongPressStop => {
-                defmt::info!("volume + 1");
-                ply.volume_add(1);
+            LongPressDuring => {
+                *scan_ticks += 1;
+                if *scan_ticks == SCAN_TICKS {
+                    *scan_ticks = 0;
+                    ply.seek_bars(1)
+                } else {
+                    Ok(())
+                }
             }
             DoubleClick => {
                 defmt::info!("next music");
-                ply.next();
+                ply.next()
+            }
+            MultiClick(3) => {
+                ply.set_mode(ply.mode().next());
+                defmt::info!("play mode {}", ply.mode());
+                Ok(())
+            }
+            MultiClick(4) => ply.mark_loop().map(|points| {
+                defmt::info!("loop {}", points);
+            }),
+            MultiClick(5) => {
+                // 练习模式：回到 A 点前打预备拍，每遍提速 5% 直到原速
+                let practice = !ply.count_in();
+                ply.set_count_in(practice);
+                ply.set_loop_speed_step(if practice { 5 } else { 0 });
+                defmt::info!("loop practice {}", practice);
+                Ok(())
+            }
+            _ => Ok(()),
+        });
+        if let Err(err) = result {
+            defmt::warn!("button B: {}", err);
+        }
+    }
+
+    /// 串口命令：只在锁内读改状态，回复先格式化到缓冲，释放锁后再交给串口发送
+    #[task(priority = 1, shared = [alarm, clock, player, serial, storage, display, ui])]
+    async fn handle_serial_command(
+        mut ctx: handle_serial_command::Context,
+        command: serial::Command,
+    ) {
+        use core::fmt::Write;
+        use serial::Command::*;
+
+        let now = Mono::now();
+        let mut reply = serial::Reply::new();
+        let out = &mut reply;
+        match command {
+            Queue(pos) => ctx.shared.player.lock(|ply| match ply.enqueue(pos) {
+                Ok(()) => writeln!(out, "queued {}", pos),
+                Err(err) => writeln!(out, "cannot queue {}: {:?}", pos, err),
+            }),
+            PlayNext(pos) => ctx.shared.player.lock(|ply| match ply.play_next(pos) {
+                Ok(()) => writeln!(out, "next {}", pos),
+                Err(err) => writeln!(out, "cannot queue {}: {:?}", pos, err),
+            }),
+            Remove(index) => match ctx.shared.player.lock(|ply| ply.dequeue_at(index)) {
+                Ok(pos) => writeln!(out, "removed {}", pos),
+                Err(err) => writeln!(out, "cannot remove {}: {:?}", index, err),
+            },
+            Clear => {
+                ctx.shared.player.lock(|ply| ply.clear_queue());
+                writeln!(out, "queue cleared")
+            }
+            List => ctx
+                .shared
+                .player
+                .lock(|ply| writeln!(out, "queue {:?}", ply.queue())),
+            Volume(volume) => {
+                let volume = ctx.shared.player.lock(|ply| {
+                    ply.set_volume(volume);
+                    ply.volume()
+                });
+                writeln!(out, "volume {}", volume)
             }
-            _ => {}
-        })
+            Seek(seconds) => {
+                let result = ctx.shared.player.lock(|ply| {
+                    ply.seek_by(seconds.saturating_mul(1000))
+                        .map(|()| ply.position_ms())
+                });
+                match result {
+                    Ok(ms) => writeln!(out, "at {} ms", ms),
+                    Err(err) => writeln!(out, "cannot seek: {:?}", err),
+                }
+            }
+            Calibrate(Some((point, duty))) => {
+                let mut calibration = ctx.shared.player.lock(|ply| ply.calibration());
+                if calibration.set(point, duty) {
+                    ctx.shared
+                        .player
+                        .lock(|ply| ply.set_calibration(calibration));
+                    ctx.shared.storage.lock(|storage| {
+                        storage.save(storage::Key::Calibration, &calibration.to_words())
+                    });
+                    writeln!(out, "calibration {:?}", calibration.0)
+                } else {
+                    writeln!(out, "cannot set point {} to {}", point, duty)
+                }
+            }
+            Calibrate(None) => {
+                let calibration = ctx.shared.player.lock(|ply| ply.calibration());
+                writeln!(out, "calibration {:?}", calibration.0)
+            }
+            Curve(curve) => {
+                ctx.shared.player.lock(|ply| ply.set_curve(curve));
+                writeln!(out, "volume curve {:?}", curve)
+            }
+            Time(time) => {
+                let now = ctx.shared.clock.lock(|clock| {
+                    if let Some(time) = time {
+                        clock.set(time);
+                    }
+                    clock.now()
+                });
+                writeln!(out, "time {}", now)
+            }
+            Alarm(slot, setting) => {
+                let result = ctx
+                    .shared
+                    .alarm
+                    .lock(|alarm| alarm.set(slot, setting).map(|()| alarm.to_words()));
+                match result {
+                    Ok(words) => {
+                        ctx.shared
+                            .storage
+                            .lock(|storage| storage.save(storage::Key::Alarms, &words));
+                        writeln!(out, "alarm {} {:?}", slot, setting)
+                    }
+                    Err(err) => writeln!(out, "cannot set alarm: {:?}", err),
+                }
+            }
+            Alarms => {
+                let (alarms, chime) = ctx
+                    .shared
+                    .alarm
+                    .lock(|alarm| (*alarm.alarms(), alarm.chime()));
+                alarms
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(slot, setting)| writeln!(out, "alarm {} {:?}", slot, setting))
+                    .and_then(|()| writeln!(out, "chime {}", chime))
+            }
+            Chime(chime) => {
+                let words = ctx.shared.alarm.lock(|alarm| {
+                    alarm.set_chime(chime);
+                    alarm.to_words()
+                });
+                ctx.shared
+                    .storage
+                    .lock(|storage| storage.save(storage::Key::Alarms, &words));
+                writeln!(out, "chime {}", chime)
+            }
+            Say(text) => {
+                (&mut ctx.shared.display, &mut ctx.shared.ui)
+                    .lock(|display, ui| ui.scroll(display, &text, &now));
+                Ok(())
+            }
+            Title => match ctx.shared.player.lock(|ply| ply.status()) {
+                status if status.pos.is_some() => {
+                    (&mut ctx.shared.display, &mut ctx.shared.ui)
+                        .lock(|display, ui| ui.scroll(display, status.title, &now));
+                    Ok(())
+                }
+                _ => writeln!(out, "stopped"),
+            },
+            Scroll(ms) => {
+                let ms = ctx.shared.ui.lock(|ui| {
+                    ui.set_scroll_ms(ms);
+                    ui.scroll_ms()
+                });
+                writeln!(out, "scroll {} ms", ms)
+            }
+        }
+        .ok();
+        ctx.shared.serial.lock(|serial| serial.write(&reply));
+    }
+
+    #[task(binds = UARTE0_UART0, shared = [serial])]
+    fn handle_serial_event(mut ctx: handle_serial_event::Context) {
+        ctx.shared.serial.lock(|serial| serial.handle_uart_event());
     }
 
     #[task(binds = TIMER1, shared = [display])]
@@ -217,7 +1033,228 @@ mod app {
             .lock(|display| display.handle_display_event());
     }
 
-    #[task(binds = TIMER2, shared = [player])]
+    /// 音叉和录音模式下左右倾斜逐个半音切换
+    #[task(priority = 1, shared = [mode])]
+    async fn handle_tilt_event(mut ctx: handle_tilt_event::Context, tilt: accel::Tilt) {
+        let semitones = match tilt {
+            accel::Tilt::Left => -1,
+            accel::Tilt::Right => 1,
+        };
+        match ctx.shared.mode.lock(|mode| *mode) {
+            mode::Mode::Tuner => tune::spawn(semitones, 0).ok(),
+            mode::Mode::Recorder => pick_note::spawn(semitones).ok(),
+            _ => None,
+        };
+    }
+
+    /// 录音换下一次按键发出的音，并显示音名
+    #[task(priority = 1, shared = [recorder, display, ui])]
+    async fn pick_note(mut ctx: pick_note::Context, semitones: i32) {
+        let name = ctx.shared.recorder.lock(|recorder| {
+            recorder.step(semitones);
+            recorder.name()
+        });
+        let now = Mono::now();
+        (ctx.shared.display, ctx.shared.ui)
+            .lock(|display, ui| ui.alternate(display, ui::note(name), &now));
+        defmt::info!("recorder {}", name);
+    }
+
+    /// 录好的曲子放到播放列表末尾，切回播放器模式播放
+    #[task(priority = 1, shared = [mode, player, playlist, display, ui])]
+    async fn play_recording(
+        mut ctx: play_recording::Context,
+        notes: heapless::Vec<(tone::Tone, i8, effect::Effect), { recorder::RECORD_NOTES }>,
+        bpm: u32,
+    ) {
+        ctx.shared.mode.lock(|mode| *mode = mode::Mode::Player);
+        let result = (ctx.shared.playlist, ctx.shared.player).lock(|playlist, ply| {
+            ply.stop_pitch();
+            let pos = playlist.publish(ply, &notes, bpm);
+            ply.play_song(pos, 0)
+        });
+        if let Err(err) = result {
+            defmt::warn!("recording: {}", err);
+        }
+        let now = Mono::now();
+        (ctx.shared.display, ctx.shared.ui).lock(|display, ui| {
+            ui.clear_pages(display);
+            ui.toast(display, &ui::mode(mode::Mode::Player), &now);
+        });
+        defmt::info!("recorded {} notes at {} bpm", notes.len(), bpm);
+    }
+
+    /// 姿态更新：倾斜乐器发声时跟随改变音高和音量
+    #[task(priority = 1, shared = [attitude, mode, player, theremin])]
+    async fn handle_attitude_event(
+        mut ctx: handle_attitude_event::Context,
+        attitude: accel::Attitude,
+    ) {
+        ctx.shared.attitude.lock(|last| *last = attitude);
+        if ctx.shared.mode.lock(|mode| *mode) == mode::Mode::Theremin {
+            (ctx.shared.theremin, ctx.shared.player)
+                .lock(|theremin, ply| play_theremin(theremin, attitude, ply));
+        }
+    }
+
+    /// 音叉换音或调整标准音，正在发声时立即换成新的音高，并显示音名
+    #[task(priority = 1, shared = [fork, player, display, ui])]
+    async fn tune(mut ctx: tune::Context, semitones: i32, a4_delta: i32) {
+        let fork = ctx.shared.fork.lock(|fork| {
+            fork.step(semitones);
+            fork.set_a4_hz(fork.a4_hz().saturating_add_signed(a4_delta));
+            *fork
+        });
+        ctx.shared.player.lock(|ply| {
+            if ply.pitch().is_some() {
+                ply.hold_pitch(fork.freq(), 100);
+            }
+        });
+        let now = Mono::now();
+        (ctx.shared.display, ctx.shared.ui)
+            .lock(|display, ui| ui.alternate(display, ui::note(fork.name()), &now));
+        defmt::info!("tuner {} (A4 = {} Hz)", fork.name(), fork.a4_hz());
+    }
+
+    /// 播放器事件：节拍器闪出每一拍；播放器模式下按状态变化刷新点阵。
+    /// 由 TIMER2 挂起 SWI1 触发，取空队列后才返回，返回前新到的事件会让它再跑一次
+    #[task(binds = SWI1_EGU1, priority = 1, shared = [mode, player, display, ui])]
+    fn handle_player_queue(mut ctx: handle_player_queue::Context) {
+        use player::Event::*;
+
+        while let Some(event) = PLAYER_EVENTS.dequeue() {
+            defmt::debug!("player event: {:?}", event);
+            let now = Mono::now();
+            let is_player = ctx.shared.mode.lock(|mode| *mode == mode::Mode::Player);
+            let status = match event {
+                SongStarted(_) | SongFinished(_) | Paused | Resumed | Stopped if is_player => {
+                    Some(ctx.shared.player.lock(|ply| ply.status()))
+                }
+                _ => None,
+            };
+            let beat = match event {
+                NoteOn { progress, .. } if is_player => {
+                    ctx.shared.player.lock(|ply| ply.beat_at(progress))
+                }
+                _ => None,
+            };
+            (&mut ctx.shared.display, &mut ctx.shared.ui).lock(|display, ui| match event {
+                Beat { beat, accent } => {
+                    ui.flash(display, &ui::beat(beat, accent), &now, BEAT_FLASH_MS)
+                }
+                VolumeChanged(volume) if is_player => ui.volume(display, volume, &now),
+                ModeChanged(mode) if is_player => ui.toast(display, &ui::play_mode(mode), &now),
+                NoteOn { tone, velocity, .. } if is_player => {
+                    ui.note_on(display, tone.freq(), velocity, beat)
+                }
+                NoteOff if is_player => ui.note_off(),
+                _ => {
+                    if let Some(status) = &status {
+                        show_status(display, ui, status, &now);
+                        // 新曲开始时滚动一遍曲名，滚完回到状态页
+                        if let SongStarted(_) = event {
+                            ui.scroll(display, status.title, &now);
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    /// 播放或暂停时轮流显示状态图标和曲目序号，停止时短暂显示停止图标
+    fn show_status(
+        display: &mut Display,
+        ui: &mut Ui,
+        status: &player::Status,
+        now: &fugit::TimerInstantU64<TIMER_HZ>,
+    ) {
+        match status.state {
+            player::PlaybackState::Stopped => {
+                ui.clear_pages(display);
+                ui.toast(display, &ui::playback(status.state), now);
+            }
+            state => {
+                let track = ui::track(status.pos.unwrap_or(0));
+                ui.alternate(display, [ui::playback(state), track], now);
+            }
+        }
+    }
+
+    /// 当前要保存的设置
+    fn snapshot_settings(
+        ply: &Player,
+        metronome: &Metronome,
+        fork: &TuningFork,
+    ) -> settings::Settings {
+        let status = ply.status();
+        let mut loops = [None; player::LOOP_SLOTS];
+        for (slot, points) in loops.iter_mut().zip(ply.loops()) {
+            *slot = Some(points);
+        }
+        settings::Settings {
+            volume: ply.muted_volume().unwrap_or(ply.volume()),
+            muted: ply.is_muted(),
+            curve: ply.curve(),
+            mode: ply.mode(),
+            speed: ply.speed(),
+            song: status.pos.map(|pos| (pos, status.progress)),
+            bpm: metronome.bpm(),
+            signature: metronome.signature(),
+            a4_hz: fork.a4_hz(),
+            loops,
+        }
+    }
+
+    /// 开机时恢复保存的设置，上次的曲子停在原来的位置
+    fn restore_settings(
+        settings: &settings::Settings,
+        ply: &mut Player,
+        metronome: &mut Metronome,
+        fork: &mut TuningFork,
+    ) {
+        ply.set_volume(settings.volume);
+        if settings.muted {
+            ply.toggle_mute();
+        }
+        ply.set_curve(settings.curve);
+        ply.set_mode(settings.mode);
+        ply.set_speed(settings.speed);
+        for &(pos, points) in settings.loops.iter().flatten() {
+            if let Err(err) = ply.set_loop(pos, points) {
+                defmt::warn!("restore loop: {}", err);
+            }
+        }
+        if let Some((pos, progress)) = settings.song {
+            if let Err(err) = ply.cue(pos, progress) {
+                defmt::warn!("restore song: {}", err);
+            }
+        }
+        metronome.set_bpm(settings.bpm);
+        if metronome::Signature::ALL.contains(&settings.signature) {
+            metronome.set_signature(settings.signature);
+        }
+        fork.set_a4_hz(settings.a4_hz);
+    }
+
+    /// 按倾斜乐器的开关和姿态发声或停止
+    fn play_theremin(theremin: &Theremin, attitude: accel::Attitude, ply: &mut Player) {
+        if theremin.gate() {
+            ply.hold_pitch(theremin.freq(attitude.y_mg), theremin.level(attitude.x_mg));
+        } else {
+            ply.stop_pitch();
+        }
+    }
+
+    /// 修改节拍器设置，节拍器正在运行时同时生效
+    fn adjust_metronome(settings: &mut Metronome, ply: &mut Player, f: impl Fn(&mut Metronome)) {
+        f(settings);
+        if let Some(running) = ply.metronome_mut() {
+            f(running);
+        }
+    }
+
+    /// 播放器的优先级高于其他任务，只有持有 `player` 锁的短暂时刻会推迟它
+    #[task(binds = TIMER2, priority = 2, shared = [player])]
     fn handle_player_event(mut ctx: handle_player_event::Context) {
         ctx.shared.player.lock(|ply| ply.handle_play_event());
     }
@@ -1,42 +1,201 @@
 use defmt::Format;
+use musicbox_core::rhythm::{Meter, Signature};
 
+use crate::drum::Drum;
+use crate::effect::Effect;
+use crate::instrument::Instrument;
+use crate::sample;
 use crate::tone::Tone;
+use crate::wavetable::{self, Wave};
 
-#[derive(Format, Debug)]
+#[derive(Format, Debug, Clone)]
 pub struct Melody {
-    whole_note_delay_ms: u32,
-    notes: &'static [(Tone, i8)],
+    /// 标识符，切换播放列表时按它找回同一首
+    name: &'static str,
+    /// 在点阵上滚动显示的标题
+    title: &'static str,
+    /// 全音符的时长、拍号和弱起，决定音符的时间和小节线
+    meter: Meter,
+    instrument: Instrument,
+    /// 音色波形，方波以外由波表合成
+    wave: Wave,
+    notes: &'static [(Tone, i8, Effect)],
+    /// 鼓点序列，按整首曲子的时间循环播放
+    drums: &'static [(Drum, i8)],
 }
 
 impl Melody {
+    /// 4/4 拍、默认音色的曲子，用于运行时录下的音符，标题与 `name` 相同
+    pub const fn new(name: &'static str, tempo: u32, notes: &'static [(Tone, i8, Effect)]) -> Self {
+        Self {
+            name,
+            title: name,
+            meter: Meter {
+                whole_ms: (60000 * 4) / tempo,
+                signature: Signature { beats: 4, unit: 4 },
+                pickup: 0,
+            },
+            instrument: Instrument::Organ,
+            wave: Wave::Square,
+            notes,
+            drums: &[],
+        }
+    }
+
     pub fn get(&self, pos: usize) -> Option<(Tone, u32)> {
-        self.notes.get(pos).cloned().map(|(note, div)| {
-            let dotted = if div > 0 { false } else { true };
-            let div = div.abs() as f32;
-            let delay_ms = self.whole_note_delay_ms as f32 / div;
-            (note, if dotted { delay_ms * 1.5 } else { delay_ms } as u32)
-        })
+        self.notes
+            .get(pos)
+            .map(|&(note, div, _)| (note, self.delay_ms(div)))
+    }
+
+    /// 第 `pos` 个音符上的音高效果
+    pub fn effect(&self, pos: usize) -> Effect {
+        self.notes
+            .get(pos)
+            .map_or(Effect::None, |&(_, _, effect)| effect)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn title(&self) -> &'static str {
+        self.title
     }
 
     pub fn len(&self) -> usize {
         self.notes.len()
     }
+
+    pub fn instrument(&self) -> Instrument {
+        self.instrument
+    }
+
+    pub fn wave(&self) -> Wave {
+        self.wave
+    }
+
+    /// 第 `pos` 个鼓点及其时长
+    pub fn drum(&self, pos: usize) -> Option<(Drum, u32)> {
+        self.drums
+            .get(pos)
+            .map(|&(drum, div)| (drum, self.delay_ms(div)))
+    }
+
+    pub fn drum_len(&self) -> usize {
+        self.drums.len()
+    }
+
+    /// 鼓点序列循环一遍的时长，没有鼓点时为 0
+    pub fn drum_cycle_ms(&self) -> u32 {
+        self.drums.iter().map(|&(_, div)| self.delay_ms(div)).sum()
+    }
+
+    /// 第 `pos` 个音符开始的时间
+    pub fn time_at(&self, pos: usize) -> u32 {
+        self.meter.time_at(self.divs(), pos)
+    }
+
+    /// 整首曲子的时长
+    pub fn duration_ms(&self) -> u32 {
+        self.time_at(self.len())
+    }
+
+    /// 第一个在曲子时间 `ms` 或之后开始的音符，超过曲尾时为 `len()`
+    pub fn pos_at(&self, ms: u32) -> usize {
+        self.meter.pos_at(self.divs(), ms)
+    }
+
+    pub fn signature(&self) -> Signature {
+        self.meter.signature
+    }
+
+    /// 从曲子时间 `ms` 所在的小节向后（正数）或向前（负数）移动 `bars` 小节，
+    /// 返回该小节第一个音符的位置，超过曲尾时为 `len()`，见 `Meter::bar_pos`
+    pub fn bar_pos(&self, ms: u32, bars: i32) -> usize {
+        self.meter.bar_pos(self.divs(), ms, bars)
+    }
+
+    /// 拍号中一拍的时长，6/8 拍为一个八分音符
+    pub fn beat_ms(&self) -> u32 {
+        self.meter.beat_ms()
+    }
+
+    /// 第 `pos` 个音符是否落在拍上：不在拍上为 `None`，落在小节第一拍为 `Some(true)`
+    pub fn beat_at(&self, pos: usize) -> Option<bool> {
+        self.meter.beat_at(self.divs(), pos)
+    }
+
+    /// 各音符的时值
+    fn divs(&self) -> impl Iterator<Item = i8> + Clone + '_ {
+        self.notes.iter().map(|&(_, div, _)| div)
+    }
+
+    fn delay_ms(&self, div: i8) -> u32 {
+        self.meter.delay_ms(div)
+    }
 }
 
 macro_rules! melody {
+    (@instrument) => {
+        Instrument::Organ
+    };
+    (@instrument $instrument:ident) => {
+        Instrument::$instrument
+    };
+    (@wave) => {
+        Wave::Square
+    };
+    (@wave $wave:ident) => {
+        Wave::$wave
+    };
+    (@wave $wave:ident $table:path) => {
+        Wave::$wave(&$table)
+    };
+    (@pickup) => {
+        0
+    };
+    (@pickup $pickup:expr) => {
+        $pickup
+    };
+    (@effect) => {
+        Effect::None
+    };
+    (@effect $effect:expr) => {
+        $effect
+    };
     (
         name = $name:ident,
+        title = $title:literal,
         tempo = $tempo:expr,
         beat = $beat:expr,
-        $([$($note:ident: $duration:expr),*]),*
+        signature = $beats:literal / $unit:literal,
+        $(pickup = $pickup:expr,)?
+        $(instrument = $instrument:ident,)?
+        $(wave = $wave:ident $(($table:path))?,)?
+        $(drums = [$($drum:ident $(($sample:path))?: $drum_duration:expr),*],)?
+        $([$($note:ident: $duration:expr $(=> $effect:expr)?),*]),*
     ) => {
         pub const $name: Melody = Melody {
-            whole_note_delay_ms: (60000 * $beat) / $tempo,
+            name: stringify!($name),
+            title: $title,
+            meter: Meter {
+                whole_ms: (60000 * $beat) / $tempo,
+                signature: Signature { beats: $beats, unit: $unit },
+                pickup: melody!(@pickup $($pickup)?),
+            },
+            instrument: melody!(@instrument $($instrument)?),
+            wave: melody!(@wave $($wave $($table)?)?),
             notes: &[
                 $(
-                    $((Tone::$note, $duration),)*
+                    $((Tone::$note, $duration, melody!(@effect $($effect)?)),)*
                 )*
-            ]
+            ],
+            drums: &[
+                $(
+                    $((Drum::$drum $((&$sample))?, $drum_duration),)*
+                )?
+            ],
         };
     };
 }
@@ -44,7 +203,11 @@ macro_rules! melody {
 // Happy birthday
 // https://musescore.com/user/8221/scores/26906
 melody!(
-    name = HAPPY_BIRTHDAY, tempo = 140, beat = 4,
+    name = HAPPY_BIRTHDAY, title = "Happy Birthday",
+    tempo = 140, beat = 4,
+    signature = 6 / 8, pickup = -4,
+    instrument = Piano,
+    wave = Triangle,
     [C4:4, C4:8, D4:-4, C4:-4, F4:-4, E4:-2],
     [C4:4, C4:8, D4:-4, C4:-4, G4:-4, F4:-2],
     [C4:4, C4:8, C5:-4, A4:-4, F4:-4, E4:-4, D4:-4],
@@ -54,7 +217,11 @@ melody!(
 // We Wish You a Merry Christmas
 // https://musescore.com/user/6208766/scores/1497501
 melody!(
-    name = MERRY_CHRISTMAS, tempo = 140, beat = 4,
+    name = MERRY_CHRISTMAS, title = "We Wish You a Merry Christmas",
+    tempo = 140, beat = 4,
+    signature = 3 / 4, pickup = 4,
+    instrument = Pluck,
+    wave = Sine,
     [C5:4, //1
     F5:4, F5:8, G5:8, F5:8, E5:8,
     D5:4, D5:4, D5:4,
@@ -121,7 +288,14 @@ melody!(
 );
 
 melody!(
-    name = SUPER_MARIOBROS, tempo = 200, beat = 4,
+    name = SUPER_MARIOBROS, title = "Super Mario Bros.",
+    tempo = 200, beat = 4,
+    signature = 4 / 4,
+    instrument = Square,
+    drums = [
+        Kick:8, HiHat:8, Snare:8, HiHat:8,
+        Kick:8, HiHat:8, Sample(sample::HIT):8, HiHat:8
+    ],
     [E5:8, E5:8, REST:8, E5:8, REST:8, C5:8, E5:8, //1
     G5:4, REST:4, G4:8, REST:4],
 
@@ -208,7 +382,11 @@ melody!(
 );
 
 melody!(
-    name = GAME_OF_THRONES, tempo = 85, beat = 4,
+    name = GAME_OF_THRONES, title = "Game of Thrones",
+    tempo = 85, beat = 4,
+    signature = 3 / 4,
+    instrument = Pad,
+    wave = Custom(wavetable::ORGAN),
     [G4:8, C4:8, DS4:16, F4:16, G4:8, C4:8, DS4:16, F4:16, //1
     G4:8, C4:8, DS4:16, F4:16, G4:8, C4:8, DS4:16, F4:16,
     G4:8, C4:8, E4:16, F4:16, G4:8, C4:8, E4:16, F4:16,
@@ -218,38 +396,38 @@ melody!(
 
     [DS4:16, F4:16, G4:4, C4:4, DS4:16, F4:16], //6
 
-    [D4:-1, //7 and 8
+    [D4:-1 => Effect::VIBRATO, //7 and 8
     F4:-4, AS3:-4,
     DS4:16, D4:16, F4:4, AS3:-4],
 
-    [DS4:16, D4:16, C4:-1], //11 and 12
+    [DS4:16, D4:16, C4:-1 => Effect::VIBRATO], //11 and 12
 
     //repeats from 5
     [G4:-4, C4:-4],//5
 
     [DS4:16, F4:16, G4:4, C4:4, DS4:16, F4:16], //6
 
-    [D4:-1, //7 and 8
+    [D4:-1 => Effect::VIBRATO, //7 and 8
     F4:-4, AS3:-4,
     DS4:16, D4:16, F4:4, AS3:-4],
 
-    [DS4:16, D4:16, C4:-1, //11 and 12
+    [DS4:16, D4:16, C4:-1 => Effect::VIBRATO, //11 and 12
     G4:-4, C4:-4,
     DS4:16, F4:16, G4:4,  C4:4, DS4:16, F4:16],
 
-    [D4:-2,//15
+    [D4:-2 => Effect::BEND_UP, //15
     F4:-4, AS3:-4,
     D4:-8, DS4:-8, D4:-8, AS3:-8,
-    C4:-1,
+    C4:-1 => Effect::VIBRATO,
     C5:-2,
     AS4:-2,
     C4:-2,
     G4:-2,
     DS4:-2,
     DS4:-4, F4:-4,
-    G4:-1],
+    G4:-1 => Effect::VIBRATO],
 
-    [C5:-2,//28
+    [C5:-2 => Effect::GLIDE, //28
     AS4:-2,
     C4:-2,
     G4:-2,
@@ -261,3 +439,16 @@ melody!(
     REST:4, GS5:16, AS5:16, C6:8, G5:8, GS5:16, AS5:16,
     C6:8, G5:16, GS5:16, AS5:16, C6:8, G5:8, GS5:16, AS5:16]
 );
+
+// Westminster Quarters, played on the hour
+melody!(
+    name = CHIME, title = "Westminster Quarters",
+    tempo = 100, beat = 4,
+    signature = 3 / 4,
+    instrument = Pluck,
+    wave = Sine,
+    [E5:4, C5:4, D5:4, G4:2, REST:4],
+    [C5:4, D5:4, E5:4, C5:2, REST:4],
+    [E5:4, D5:4, C5:4, G4:2, REST:4],
+    [G4:4, D5:4, E5:4, C5:2]
+);
@@ -0,0 +1,124 @@
+use defmt::Format;
+use fugit::{TimerDurationU64, TimerInstantU64};
+
+use crate::tone::Tone;
+
+pub use musicbox_core::rhythm::Signature;
+
+/// 节拍器速度范围
+pub const BPM_MIN: u32 = 30;
+pub const BPM_MAX: u32 = 250;
+/// 重拍和其余拍子的音，音高明显不同
+pub const ACCENT_TONE: Tone = Tone::C7;
+pub const BEAT_TONE: Tone = Tone::C6;
+
+/// 节拍器：按速度和拍号依次给出每一拍，每小节第一拍为重拍
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metronome {
+    bpm: u32,
+    signature: Signature,
+    /// 下一拍在小节中的位置
+    beat: u32,
+}
+
+impl Metronome {
+    pub fn new(bpm: u32, signature: Signature) -> Self {
+        Self {
+            bpm: bpm.clamp(BPM_MIN, BPM_MAX),
+            signature,
+            beat: 0,
+        }
+    }
+
+    pub fn bpm(&self) -> u32 {
+        self.bpm
+    }
+
+    /// 修改速度，从下一拍起生效
+    pub fn set_bpm(&mut self, bpm: u32) {
+        self.bpm = bpm.clamp(BPM_MIN, BPM_MAX);
+    }
+
+    pub fn signature(&self) -> Signature {
+        self.signature
+    }
+
+    /// 修改拍号，从下一小节的重拍重新数
+    pub fn set_signature(&mut self, signature: Signature) {
+        self.signature = signature;
+        self.beat = 0;
+    }
+
+    /// 一拍的时长（微秒）
+    pub fn beat_us(&self) -> u32 {
+        60_000_000 / self.bpm
+    }
+
+    /// 取出下一拍：在小节中的位置，以及是否为重拍
+    pub fn next_beat(&mut self) -> (u32, bool) {
+        let beat = self.beat;
+        self.beat = (beat + 1) % self.signature.beats.max(1);
+        (beat, beat == 0 && self.signature.beats > 1)
+    }
+}
+
+/// 参与平均的敲击间隔数
+const TAP_INTERVALS: usize = 4;
+/// 两次敲击间隔超过该时间时重新开始计
+const TAP_TIMEOUT_MS: u64 = 2000;
+
+/// 敲击测速：取最近几次敲击间隔的平均值。
+/// 按下时只记下时刻，确认是单击后才算一次敲击，多击手势的按键不会打乱测速
+pub struct TapTempo<const TIMER_HZ: u32> {
+    /// 还没确认是不是单击的按下时刻
+    pressed: Option<TimerInstantU64<TIMER_HZ>>,
+    last: Option<TimerInstantU64<TIMER_HZ>>,
+    intervals: [TimerDurationU64<TIMER_HZ>; TAP_INTERVALS],
+    count: usize,
+}
+
+impl<const TIMER_HZ: u32> TapTempo<TIMER_HZ> {
+    pub fn new() -> Self {
+        Self {
+            pressed: None,
+            last: None,
+            intervals: [TimerDurationU64::from_ticks(0); TAP_INTERVALS],
+            count: 0,
+        }
+    }
+
+    /// 按下按键，记下时刻
+    pub fn press(&mut self, now: &TimerInstantU64<TIMER_HZ>) {
+        self.pressed = Some(*now);
+    }
+
+    /// 上一次按下确认为单击，按按下的时刻计入一次敲击，至少敲两下后返回测得的速度
+    pub fn commit(&mut self) -> Option<u32> {
+        let pressed = self.pressed.take()?;
+        self.tap(&pressed)
+    }
+
+    /// 这几次按下属于多击或长按，丢弃并从头开始计
+    pub fn discard(&mut self) {
+        self.pressed = None;
+        self.last = None;
+        self.count = 0;
+    }
+
+    /// 记录一次敲击，至少敲两下后返回测得的速度
+    fn tap(&mut self, now: &TimerInstantU64<TIMER_HZ>) -> Option<u32> {
+        let last = self.last.replace(*now);
+        let interval = now.checked_duration_since(last?)?;
+        if interval.to_millis() > TAP_TIMEOUT_MS {
+            self.count = 0;
+            return None;
+        }
+
+        self.intervals[self.count % TAP_INTERVALS] = interval;
+        self.count += 1;
+        let intervals = &self.intervals[..self.count.min(TAP_INTERVALS)];
+        let total_us: u64 = intervals.iter().map(|interval| interval.to_micros()).sum();
+        let average_us = total_us / intervals.len() as u64;
+        (average_us > 0).then(|| (60_000_000 / average_us) as u32)
+    }
+}
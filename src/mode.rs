@@ -0,0 +1,28 @@
+use defmt::Format;
+
+/// 整机的工作模式，按键和摇晃在不同模式下含义不同
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// 音乐播放器
+    Player,
+    /// 节拍器
+    Metronome,
+    /// 音叉，持续发出参考音
+    Tuner,
+    /// 倾斜乐器，音高跟随倾斜
+    Theremin,
+    /// 录音，把按出的节奏和音记成曲子
+    Recorder,
+}
+
+impl Mode {
+    pub fn next(self) -> Self {
+        match self {
+            Mode::Player => Mode::Metronome,
+            Mode::Metronome => Mode::Tuner,
+            Mode::Tuner => Mode::Theremin,
+            Mode::Theremin => Mode::Recorder,
+            Mode::Recorder => Mode::Player,
+        }
+    }
+}
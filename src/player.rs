@@ -2,28 +2,267 @@ use bsp::hal::{
     gpio::{Output, Pin, PushPull},
     pwm, timer,
 };
+use defmt::Format;
 use fugit::ExtU32;
+use heapless::{LinearMap, Vec};
 
 use self::inner::{PlayerBuzzer, PlayerTimer};
-use crate::{melody::Melody, tone::Tone};
+use crate::{
+    drum::{self, Sound},
+    effect::Effect,
+    instrument::{Envelope, Instrument},
+    melody::Melody,
+    metronome::{Metronome, ACCENT_TONE, BEAT_TONE},
+    rand::Rand,
+    sample::{Sample, SAMPLE_RATE},
+    tone::Tone,
+    voice::{Voice, FREQ_SCALE},
+    volume::{Calibration, Curve, GAIN_MAX},
+    wavetable::{Oscillator, Wave, Wavetable},
+};
 
 type Instant = fugit::Instant<u32, 1, 1_000_000>;
 type Duration = fugit::Duration<u32, 1, 1_000_000>;
 
 const DEFAULT_PLAY_DURATION: Duration = Duration::from_ticks(1 * 1000 * 1000);
+/// 合成器控制节拍，鼓点扫频跳频和音高效果的续写按此周期检查
+const TICK_DURATION: Duration = Duration::from_ticks(1000);
+
+/// 播放器操作失败的原因
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// 播放列表为空
+    EmptyList,
+    /// 播放列表中没有这首曲子
+    NoSuchSong(usize),
+    /// 正在播放
+    AlreadyPlaying,
+    /// 没有在播放
+    NotPlaying,
+    /// 停止状态下没有当前曲子
+    Stopped,
+    /// 播放队列已满
+    QueueFull,
+    /// 播放队列中没有这一项
+    NoSuchQueueItem(usize),
+}
+
+/// 播放器事件，在 TIMER2 中断等上下文中同步回调，回调里不要做耗时的事
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// 第 `progress` 个音符开始发声，`velocity` 为发声时听感上的响度（0-100），
+    /// 即音量乘以淡入淡出的进度，不随音量曲线换算成振幅
+    NoteOn {
+        tone: Tone,
+        progress: usize,
+        velocity: u32,
+    },
+    NoteOff,
+    /// 开始播放第 `pos` 首曲子
+    SongStarted(usize),
+    /// 第 `pos` 首曲子播放结束
+    SongFinished(usize),
+    Paused,
+    Resumed,
+    /// 停止，没有当前曲子
+    Stopped,
+    VolumeChanged(u32),
+    /// 播放模式改变
+    ModeChanged(PlayMode),
+    /// 节拍器打出小节中的第 `beat` 拍
+    Beat {
+        beat: u32,
+        accent: bool,
+    },
+}
+
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// 播放器状态快照，开销很小，可以在显示刷新中轮询
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub state: PlaybackState,
+    /// 当前曲目下标，停止时为空
+    pub pos: Option<usize>,
+    pub name: &'static str,
+    /// 曲子的标题
+    pub title: &'static str,
+    pub instrument: Instrument,
+    /// 当前音符下标及曲子的音符总数
+    pub progress: usize,
+    pub notes: usize,
+    /// 正在发声的音
+    pub tone: Option<Tone>,
+    pub elapsed_ms: u32,
+    pub total_ms: u32,
+    pub mode: PlayMode,
+    /// 播放速度，百分比
+    pub speed: u32,
+    pub volume: u32,
+}
+
+/// 一首曲子播放结束后的行为
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayMode {
+    /// 单曲循环
+    RepeatOne,
+    /// 列表循环，自动播放下一首
+    RepeatAll,
+    /// 随机播放，一轮之内不重复
+    Shuffle,
+    /// 播放一遍后停止
+    Once,
+}
+
+impl PlayMode {
+    pub fn next(self) -> Self {
+        match self {
+            PlayMode::RepeatOne => PlayMode::RepeatAll,
+            PlayMode::RepeatAll => PlayMode::Shuffle,
+            PlayMode::Shuffle => PlayMode::Once,
+            PlayMode::Once => PlayMode::RepeatOne,
+        }
+    }
+}
+
+/// 播放队列的长度
+const QUEUE_LEN: usize = 8;
+
+/// 淡入淡出期间每隔多少毫秒按新的增益重新渲染
+const FADE_STEP_MS: u32 = 5;
+
+/// 淡出结束后要做的事
+#[derive(Clone, Copy)]
+enum After {
+    Nothing,
+    Pause,
+    Stop,
+    /// 切换到第 `pos` 首并淡入
+    Start(usize),
+}
+
+/// 进行中的淡入或淡出，电平为千分比
+struct Fade {
+    from: u32,
+    to: u32,
+    duration_ms: u32,
+    elapsed_ms: u32,
+    after: After,
+}
+
+impl Fade {
+    fn level(&self) -> u32 {
+        if self.elapsed_ms >= self.duration_ms {
+            self.to
+        } else if self.to >= self.from {
+            self.from + (self.to - self.from) * self.elapsed_ms / self.duration_ms
+        } else {
+            self.from - (self.from - self.to) * self.elapsed_ms / self.duration_ms
+        }
+    }
+}
 
+/// 最多为多少首曲子保存 A-B 循环点
+pub const LOOP_SLOTS: usize = 8;
+/// 循环预备拍的音高
+const COUNT_IN_HZ: u32 = 2000;
+
+/// A-B 循环的两个点（曲子时间），只标记了 A 时 `b_ms` 为空
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loop {
+    pub a_ms: u32,
+    pub b_ms: Option<u32>,
+}
+
+/// 随机播放记录已播放曲目的位数，超出的曲目不参与随机
+const SHUFFLE_MAX: usize = 32;
+
+#[derive(Clone, Copy)]
 enum State {
     Play { pos: usize, progress: usize },
     Pause { pos: usize, progress: usize },
     Stop,
 }
 
+/// 插播的短旋律（整点报时），播完后回到原来的状态
+struct Jingle<'a> {
+    melody: &'a Melody,
+    resume: State,
+}
+
+/// 播放速度的范围和切换档位（百分比）
+const SPEED_MIN: u32 = 50;
+const SPEED_MAX: u32 = 200;
+const SPEED_STEPS: [u32; 6] = [50, 75, 100, 125, 150, 200];
+
+/// 正在发声的音符在曲子中的起始时间和时长，以及它实际开始的时刻
+struct Clock {
+    tone: Tone,
+    note_ms: u32,
+    delay_ms: u32,
+    started: Instant,
+    /// 最近一次改变速度的时刻，以及当时音符已经走过的曲子时间
+    synced: (Instant, u32),
+}
+
 pub struct Player<'a, T: timer::Instance, P: pwm::Instance> {
     list: &'a [Melody],
     state: State,
     volume: u32,
+    /// 音量到增益的曲线
+    curve: Curve,
+    /// 静音前的音量
+    muted: Option<u32>,
+    fade: Option<Fade>,
+    /// 暂停、停止、恢复时的淡入淡出时长
+    fade_ms: u32,
+    /// 切歌时的淡出和淡入时长
+    song_fade_ms: u32,
+    /// 当前曲子播完后停止，不再按播放模式继续
+    stop_after_song: bool,
     timer: PlayerTimer<T>,
     buzzer: PlayerBuzzer<P>,
+    rand: Rand,
+    /// 当前旋律音符，鼓点结束后恢复发声
+    voice: Option<Voice>,
+    /// 上一个发声音符的频率，作为滑音的起点
+    last_freq: u32,
+    /// 下一个音符在曲子中的起始时间
+    elapsed_ms: u32,
+    /// 当前曲子的总时长，开始播放时计算
+    total_ms: u32,
+    clock: Option<Clock>,
+    drums: drum::Track,
+    /// 当前曲子音色的包络
+    envelope: Envelope,
+    /// 当前曲子音色的波形
+    wave: Wave,
+    /// 播放速度，百分比
+    speed: u32,
+    mode: PlayMode,
+    /// 随机播放本轮还没有播过的曲目，按位记录
+    unplayed: u32,
+    /// 每首曲子的 A-B 循环点，以曲目下标为键
+    loops: LinearMap<usize, Loop, LOOP_SLOTS>,
+    /// 每次回到 A 点前是否先打一小节预备拍
+    count_in: bool,
+    /// 还要打的预备拍数
+    count_in_beats: u32,
+    /// 每循环一遍提高的播放速度（百分点），最多提到原速
+    loop_speed_step: u32,
+    attach_event_fn: Option<fn(Event)>,
+    /// 临时加入的待播曲目，优先于播放列表
+    queue: Vec<usize, QUEUE_LEN>,
+    jingle: Option<Jingle<'a>>,
+    /// 节拍器运行时不播放曲子
+    metronome: Option<Metronome>,
+    /// 持续发出的音，频率单位 1/`FREQ_SCALE` Hz，以及相对当前音量的电平（百分比）
+    pitch: Option<(u32, u32)>,
 }
 
 impl<'a, T: timer::Instance, P: pwm::Instance> Player<'a, T, P> {
@@ -34,11 +273,43 @@ impl<'a, T: timer::Instance, P: pwm::Instance> Player<'a, T, P> {
             list,
             state: State::Stop,
             volume: 20,
+            curve: Curve::Perceptual,
+            muted: None,
+            fade: None,
+            fade_ms: 300,
+            song_fade_ms: 100,
+            stop_after_song: false,
             timer,
             buzzer,
+            rand: Rand::new(0),
+            voice: None,
+            last_freq: 0,
+            elapsed_ms: 0,
+            total_ms: 0,
+            clock: None,
+            drums: drum::Track::new(),
+            envelope: Instrument::Organ.envelope(),
+            wave: Wave::Square,
+            speed: 100,
+            mode: PlayMode::RepeatOne,
+            unplayed: 0,
+            loops: LinearMap::new(),
+            count_in: false,
+            count_in_beats: 0,
+            loop_speed_step: 0,
+            attach_event_fn: None,
+            queue: Vec::new(),
+            jingle: None,
+            metronome: None,
+            pitch: None,
         }
     }
 
+    /// 设置伪随机数种子，用于鼓点的随机跳频
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rand = Rand::new(seed);
+    }
+
     pub fn is_playing(&self) -> bool {
         match self.state {
             State::Play { .. } => true,
@@ -46,127 +317,1030 @@ impl<'a, T: timer::Instance, P: pwm::Instance> Player<'a, T, P> {
         }
     }
 
+    pub fn attach_event(&mut self, f: fn(Event)) {
+        self.attach_event_fn = Some(f);
+    }
+
     pub fn volume_add(&mut self, volume: u32) {
-        self.volume = self.volume.saturating_add(volume).min(100);
+        let current = self.muted.take().unwrap_or(self.volume);
+        self.set_volume(current.saturating_add(volume).min(100));
     }
 
     pub fn volume_sub(&mut self, volume: u32) {
-        self.volume = self.volume.saturating_sub(volume);
+        let current = self.muted.take().unwrap_or(self.volume);
+        self.set_volume(current.saturating_sub(volume));
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.is_some()
+    }
+
+    /// 静音前的音量，没有静音时为空
+    pub fn muted_volume(&self) -> Option<u32> {
+        self.muted
+    }
+
+    /// 静音，再次调用恢复静音前的音量
+    pub fn toggle_mute(&mut self) {
+        match self.muted.take() {
+            Some(volume) => self.set_volume(volume),
+            None => {
+                self.muted = Some(self.volume);
+                self.set_volume(0);
+            }
+        }
+    }
+
+    /// 设置暂停、停止、恢复时的淡入淡出时长，0 为直接切断
+    pub fn set_fade_ms(&mut self, fade_ms: u32) {
+        self.fade_ms = fade_ms;
+    }
+
+    /// 设置切歌时的淡出和淡入时长，0 为直接切换
+    pub fn set_song_fade_ms(&mut self, fade_ms: u32) {
+        self.song_fade_ms = fade_ms;
+    }
+
+    /// 直接设置音量（0-100），不改变静音状态
+    pub fn set_volume(&mut self, volume: u32) {
+        let volume = volume.min(100);
+        if volume != self.volume {
+            self.volume = volume;
+            if let Some((freq, level)) = self.pitch {
+                self.buzzer.pitch(freq, self.gain() * level / 100);
+            }
+            self.emit(Event::VolumeChanged(volume));
+        }
     }
 
     pub fn volume(&self) -> u32 {
         self.volume
     }
 
+    pub fn curve(&self) -> Curve {
+        self.curve
+    }
+
+    /// 切换音量曲线，下一次发声时生效
+    pub fn set_curve(&mut self, curve: Curve) {
+        self.curve = curve;
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        self.buzzer.calibration()
+    }
+
+    /// 替换本板扬声器的占空比校准表
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.buzzer.set_calibration(calibration);
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    /// 设置播放速度（50%-200%），播放中修改时从当前位置继续
+    pub fn set_speed(&mut self, speed: u32) {
+        let speed = speed.clamp(SPEED_MIN, SPEED_MAX);
+        if speed == self.speed {
+            return;
+        }
+
+        if let Some(clock) = &mut self.clock {
+            let now = self.timer.now();
+            let offset_ms =
+                clock.synced.1 + Self::scale(self.speed, (now - clock.synced.0).to_millis());
+            clock.synced = (now, offset_ms);
+
+            // 按新速度重新安排当前音符剩余的时长
+            let release_ms = clock.delay_ms * 9 / 10;
+            let remaining_ms = clock.delay_ms.saturating_sub(offset_ms);
+            self.timer
+                .set_play_duration((remaining_ms * 100_000 / speed).micros());
+            if offset_ms < release_ms {
+                self.timer
+                    .set_next_duration(((release_ms - offset_ms) * 100_000 / speed).micros());
+            }
+        }
+        self.speed = speed;
+    }
+
+    /// 切换到下一档播放速度
+    pub fn next_speed(&mut self) {
+        let speed = SPEED_STEPS
+            .into_iter()
+            .find(|&speed| speed > self.speed)
+            .unwrap_or(SPEED_STEPS[0]);
+        self.set_speed(speed);
+    }
+
+    pub fn mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PlayMode) {
+        self.mode = mode;
+        self.unplayed = 0;
+        self.emit(Event::ModeChanged(mode));
+    }
+
+    /// 切换播放列表：当前曲子在新列表中时继续播放，否则停止。
+    /// 循环点和播放队列按曲名迁移到新列表的下标。
+    /// 返回后不再引用旧列表：插播先结束，播放器也从不向外交出列表里曲子的引用，
+    /// `Playlist` 靠这一点改写换下来的录音缓冲
     pub fn set_list(&mut self, list: &'a [Melody]) {
-        self.stop();
+        self.end_jingle();
+        let old = self.list;
+        let find = |pos: usize| {
+            let name = old.get(pos)?.name();
+            list.iter().position(|melody| melody.name() == name)
+        };
+
+        let mut loops = LinearMap::new();
+        for (&pos, &points) in self.loops.iter() {
+            if let Some(pos) = find(pos) {
+                loops.insert(pos, points).ok();
+            }
+        }
+        self.loops = loops;
+        self.queue.retain_mut(|pos| match find(*pos) {
+            Some(new_pos) => {
+                *pos = new_pos;
+                true
+            }
+            None => false,
+        });
+        self.unplayed = 0;
+
+        self.state = match self.state {
+            State::Play { pos, progress } => match find(pos) {
+                Some(pos) => State::Play { pos, progress },
+                None => {
+                    self.stop_now();
+                    self.emit(Event::Stopped);
+                    State::Stop
+                }
+            },
+            State::Pause { pos, progress } => match find(pos) {
+                Some(pos) => State::Pause { pos, progress },
+                None => {
+                    self.emit(Event::Stopped);
+                    State::Stop
+                }
+            },
+            State::Stop => State::Stop,
+        };
         self.list = list;
     }
 
-    pub fn play_or_resume(&mut self) {
+    /// 清掉第 `pos` 首的循环点并把它移出队列，用于同名的曲子换了内容
+    pub fn forget(&mut self, pos: usize) {
+        self.loops.remove(&pos);
+        self.queue.retain(|&queued| queued != pos);
+    }
+
+    /// 把第 `pos` 首加到队列末尾
+    pub fn enqueue(&mut self, pos: usize) -> Result<(), Error> {
+        self.check_song(pos)?;
+        self.queue.push(pos).map_err(|_| Error::QueueFull)
+    }
+
+    /// 把第 `pos` 首插到队列最前面，当前曲子结束后播放
+    pub fn play_next(&mut self, pos: usize) -> Result<(), Error> {
+        self.check_song(pos)?;
+        self.queue.insert(0, pos).map_err(|_| Error::QueueFull)
+    }
+
+    /// 移除队列中第 `index` 项，返回被移除的曲目
+    pub fn dequeue_at(&mut self, index: usize) -> Result<usize, Error> {
+        if index < self.queue.len() {
+            Ok(self.queue.remove(index))
+        } else {
+            Err(Error::NoSuchQueueItem(index))
+        }
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn queue(&self) -> &[usize] {
+        &self.queue
+    }
+
+    /// 在当前位置标记循环点：依次标记 A、B，第三次清除循环
+    pub fn mark_loop(&mut self) -> Result<Option<Loop>, Error> {
+        let pos = self.current_pos()?;
+        let now_ms = self.position_ms();
+
+        let next = match self.loops.get(&pos) {
+            None => Some(Loop {
+                a_ms: now_ms,
+                b_ms: None,
+            }),
+            Some(&Loop { a_ms, b_ms: None }) if now_ms > a_ms => Some(Loop {
+                a_ms,
+                b_ms: Some(now_ms),
+            }),
+            // B 点不能在 A 点之前，重新标记 A 点
+            Some(Loop { b_ms: None, .. }) => Some(Loop {
+                a_ms: now_ms,
+                b_ms: None,
+            }),
+            Some(_) => None,
+        };
+
+        match next {
+            Some(next) => {
+                if self.loops.len() == LOOP_SLOTS && !self.loops.contains_key(&pos) {
+                    // 存满时腾出一首曲子的位置
+                    let evicted = self.loops.keys().next().copied();
+                    if let Some(evicted) = evicted {
+                        self.loops.remove(&evicted);
+                    }
+                }
+                self.loops.insert(pos, next).ok();
+            }
+            None => {
+                self.loops.remove(&pos);
+            }
+        }
+        Ok(next)
+    }
+
+    /// 当前曲子的循环点
+    pub fn loop_points(&self) -> Option<Loop> {
+        let pos = self.current_pos().ok()?;
+        self.loops.get(&pos).copied()
+    }
+
+    /// 各曲子的循环点
+    pub fn loops(&self) -> impl Iterator<Item = (usize, Loop)> + '_ {
+        self.loops.iter().map(|(&pos, &points)| (pos, points))
+    }
+
+    /// 设置第 `pos` 首曲子的循环点，用于恢复保存的设置，存满时忽略
+    pub fn set_loop(&mut self, pos: usize, points: Loop) -> Result<(), Error> {
+        self.check_song(pos)?;
+        self.loops.insert(pos, points).ok();
+        Ok(())
+    }
+
+    pub fn clear_loop(&mut self) -> Result<(), Error> {
+        let pos = self.current_pos()?;
+        self.loops.remove(&pos);
+        Ok(())
+    }
+
+    pub fn count_in(&self) -> bool {
+        self.count_in
+    }
+
+    /// 每次回到 A 点前是否先打一小节预备拍
+    pub fn set_count_in(&mut self, count_in: bool) {
+        self.count_in = count_in;
+    }
+
+    /// 每循环一遍提高的播放速度（百分点），0 为不变
+    pub fn set_loop_speed_step(&mut self, step: u32) {
+        self.loop_speed_step = step;
+    }
+
+    pub fn play_or_resume(&mut self) -> Result<(), Error> {
+        self.end_jingle();
         match self.state {
-            State::Stop => self.start(0, 0),
-            State::Pause { pos, progress } => self.start(pos, progress),
-            _ => {}
+            State::Stop => {
+                self.check_song(0)?;
+                self.start_song(0);
+            }
+            State::Pause { pos, progress } => {
+                self.check_song(pos)?;
+                self.start(pos, progress);
+                self.emit(Event::Resumed);
+            }
+            State::Play { .. } => return Err(Error::AlreadyPlaying),
         }
+        self.fade_in(self.fade_ms);
+        Ok(())
     }
 
-    pub fn pause(&mut self) {
-        if let Some(next_state) = match self.state {
-            State::Play { pos, progress } => Some(State::Pause { pos, progress }),
-            _ => None,
-        } {
-            self.timer.stop();
-            self.buzzer.stop();
-            self.state = next_state;
+    /// 停在第 `pos` 首的第 `progress` 个音符处，恢复播放时从这里继续
+    pub fn cue(&mut self, pos: usize, progress: usize) -> Result<(), Error> {
+        self.check_song(pos)?;
+        self.end_jingle();
+        self.stop_now();
+        let progress = self
+            .melody(pos)
+            .map_or(0, |melody| progress.min(melody.len()));
+        self.state = State::Pause { pos, progress };
+        self.emit(Event::Paused);
+        Ok(())
+    }
+
+    /// 从头播放第 `pos` 首，用 `fade_ms` 毫秒从静音渐强到当前音量
+    pub fn play_song(&mut self, pos: usize, fade_ms: u32) -> Result<(), Error> {
+        self.check_song(pos)?;
+        self.end_jingle();
+        self.stop_now();
+        self.start_song(pos);
+        self.fade_in(fade_ms);
+        Ok(())
+    }
+
+    /// 插播一段短旋律，播完后回到原来的暂停或停止状态。
+    /// 正在播放时不打断
+    pub fn play_jingle(&mut self, melody: &'a Melody) -> Result<(), Error> {
+        if self.is_playing() {
+            return Err(Error::AlreadyPlaying);
         }
+        let resume = self.state;
+        self.stop_now();
+        self.jingle = Some(Jingle { melody, resume });
+        let pos = match resume {
+            State::Pause { pos, .. } => pos,
+            _ => 0,
+        };
+        self.start(pos, 0);
+        Ok(())
     }
 
-    /// 下一曲
-    pub fn next(&mut self) {
-        let next_pos = self.get_next_pos();
-        self.stop();
-        self.start(next_pos, 0);
+    /// 停止播放，开始打节拍器，与曲子共用同一个硬件定时器
+    pub fn start_metronome(&mut self, metronome: Metronome) {
+        self.end_jingle();
+        self.stop_now();
+        self.metronome = Some(metronome);
+        self.timer.start();
+        self.timer.set_play_duration(TICK_DURATION);
+    }
+
+    pub fn stop_metronome(&mut self) {
+        if self.metronome.take().is_some() {
+            self.stop_now();
+        }
+    }
+
+    pub fn metronome(&self) -> Option<&Metronome> {
+        self.metronome.as_ref()
+    }
+
+    /// 运行中修改节拍器的速度和拍号，从下一拍起生效
+    pub fn metronome_mut(&mut self) -> Option<&mut Metronome> {
+        self.metronome.as_mut()
+    }
+
+    /// 停止播放，用蜂鸣器持续发出频率为 `freq`（1/`FREQ_SCALE` Hz）的音，
+    /// 音量为当前音量的 `level`%，不经过曲子的定时和包络。
+    /// 已经在发声时直接改变音高和音量，不会中断
+    pub fn hold_pitch(&mut self, freq: u32, level: u32) {
+        if self.pitch.is_none() {
+            self.end_jingle();
+            self.metronome = None;
+            self.stop_now();
+        }
+        let level = level.min(100);
+        self.pitch = Some((freq, level));
+        self.buzzer.pitch(freq, self.gain() * level / 100);
+    }
+
+    /// 正在发出的音的频率
+    pub fn pitch(&self) -> Option<u32> {
+        self.pitch.map(|(freq, _)| freq)
+    }
+
+    pub fn stop_pitch(&mut self) {
+        if self.pitch.is_some() {
+            self.stop_now();
+        }
+    }
+
+    /// 淡出后暂停，正在插播时直接结束插播
+    pub fn pause(&mut self) -> Result<(), Error> {
+        if self.end_jingle() {
+            return Ok(());
+        }
+        if !self.is_playing() {
+            return Err(Error::NotPlaying);
+        }
+        self.fade_to(0, self.fade_ms, After::Pause);
+        Ok(())
+    }
+
+    /// 淡出后停止，回到第一首之前
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.stop_with_fade(self.fade_ms)
+    }
+
+    /// 用 `fade_ms` 毫秒淡出后停止
+    pub fn stop_with_fade(&mut self, fade_ms: u32) -> Result<(), Error> {
+        self.end_jingle();
+        match self.state {
+            State::Play { .. } => self.fade_to(0, fade_ms, After::Stop),
+            State::Pause { .. } => {
+                self.stop_now();
+                self.emit(Event::Stopped);
+            }
+            State::Stop => return Err(Error::Stopped),
+        }
+        Ok(())
+    }
+
+    pub fn status(&self) -> Status {
+        let (state, pos, progress) = match self.state {
+            State::Play { pos, progress } => (PlaybackState::Playing, Some(pos), progress),
+            State::Pause { pos, progress } => (PlaybackState::Paused, Some(pos), progress),
+            State::Stop => (PlaybackState::Stopped, None, 0),
+        };
+        let melody = pos.and_then(|pos| self.melody(pos));
+        let total_ms = match self.state {
+            State::Play { .. } => self.total_ms,
+            _ => melody.map_or(0, |melody| melody.duration_ms()),
+        };
+        let tone = self
+            .clock
+            .as_ref()
+            .filter(|_| self.voice.is_some())
+            .map(|clock| clock.tone);
+
+        Status {
+            state,
+            pos,
+            name: melody.map_or("", |melody| melody.name()),
+            title: melody.map_or("", |melody| melody.title()),
+            instrument: melody.map_or(Instrument::Organ, |melody| melody.instrument()),
+            progress,
+            notes: melody.map_or(0, |melody| melody.len()),
+            tone,
+            elapsed_ms: self.position_ms(),
+            total_ms,
+            mode: self.mode,
+            speed: self.speed,
+            volume: self.volume,
+        }
+    }
+
+    /// 当前曲子（插播时为插播的旋律）第 `progress` 个音符是否落在拍上，见 `Melody::beat_at`
+    pub fn beat_at(&self, progress: usize) -> Option<bool> {
+        match self.state {
+            State::Play { pos, .. } | State::Pause { pos, .. } => {
+                self.melody(pos)?.beat_at(progress)
+            }
+            State::Stop => None,
+        }
+    }
+
+    /// 当前在曲子中的时间
+    pub fn position_ms(&self) -> u32 {
+        match self.state {
+            State::Play { .. } => self.clock.as_ref().map_or(self.elapsed_ms, |clock| {
+                clock.note_ms + self.note_offset_ms(clock)
+            }),
+            State::Pause { pos, progress } => self
+                .melody(pos)
+                .map_or(0, |melody| melody.time_at(progress)),
+            State::Stop => 0,
+        }
+    }
+
+    /// 快进（正数）或快退（负数）若干毫秒
+    pub fn seek_by(&mut self, delta_ms: i32) -> Result<(), Error> {
+        let ms = self.position_ms().saturating_add_signed(delta_ms);
+        self.seek(ms)
+    }
+
+    /// 快进（正数）或快退（负数）若干小节，落在小节开头
+    pub fn seek_bars(&mut self, bars: i32) -> Result<(), Error> {
+        let melody = self.melody(self.current_pos()?).ok_or(Error::Stopped)?;
+        let progress = melody.bar_pos(self.position_ms(), bars);
+        self.seek(melody.time_at(progress))
+    }
+
+    /// 跳到曲子时间 `ms` 之后的第一个音符，不回到曲首
+    pub fn seek(&mut self, ms: u32) -> Result<(), Error> {
+        let melody = self.melody(self.current_pos()?).ok_or(Error::Stopped)?;
+        match self.state {
+            State::Play { pos, .. } => {
+                let progress = melody.pos_at(ms);
+                self.buzzer.stop();
+                self.voice = None;
+                // 下一个音符开始前忽略上一个音符残留的 next 事件
+                self.clock = None;
+                self.count_in_beats = 0;
+                self.state = State::Play { pos, progress };
+                self.elapsed_ms = melody.time_at(progress);
+                self.drums.seek(melody, self.elapsed_ms);
+                self.timer.set_play_duration(TICK_DURATION);
+            }
+            State::Pause { pos, .. } => {
+                let progress = melody.pos_at(ms);
+                self.state = State::Pause { pos, progress };
+            }
+            State::Stop => {}
+        }
+        Ok(())
+    }
+
+    /// 当前曲子播完后停止
+    pub fn set_stop_after_song(&mut self, stop: bool) {
+        self.stop_after_song = stop;
+    }
+
+    /// 是否还在等当前曲子播完，曲子播完停下后变为 `false`
+    pub fn stops_after_song(&self) -> bool {
+        self.stop_after_song
+    }
+
+    /// 下一曲，先播队列，随机播放时随机选择
+    pub fn next(&mut self) -> Result<(), Error> {
+        self.end_jingle();
+        let next_pos = match self.pop_queue() {
+            Some(pos) => pos,
+            None if self.mode == PlayMode::Shuffle => self.get_shuffle_pos()?,
+            None => self.get_next_pos()?,
+        };
+        self.change_song(next_pos);
+        Ok(())
     }
 
     /// 上一曲
-    pub fn prev(&mut self) {
-        let prev_pos = self.get_prev_pos();
-        self.stop();
-        self.start(prev_pos, 0);
+    pub fn prev(&mut self) -> Result<(), Error> {
+        self.end_jingle();
+        let prev_pos = self.get_prev_pos()?;
+        self.change_song(prev_pos);
+        Ok(())
     }
 
     pub fn handle_play_event(&mut self) {
         defmt::debug!("player::tick {}", self.timer.now());
+        if self.metronome.is_some() {
+            self.handle_beat();
+            return;
+        }
         if let State::Play { pos, progress } = self.state {
             let play_fired = self.timer.check_play();
             let next_fired = self.timer.check_next();
+            let tick_fired = self.timer.check_tick();
 
-            if let Some(melody) = self.list.get(pos) {
+            if let Some(melody) = self.melody(pos) {
                 if play_fired {
-                    let buzzer = &self.buzzer;
-                    let timer = &self.timer;
-                    if let Some((tone, delay_ms)) = melody.get(progress) {
+                    if self.count_in_beats > 0 {
+                        self.count_in_beats -= 1;
+                        self.count_in_click(melody);
+                    } else if let Some(a_ms) = self.loop_restart(pos) {
+                        self.restart_loop(pos, melody, a_ms);
+                    } else if let Some((tone, delay_ms)) = melody.get(progress) {
                         // play that note for 90% duration, leaving 10% pause
-                        buzzer.tone(tone, self.volume);
-                        timer.set_play_duration((delay_ms * 1_000).micros());
-                        timer.set_next_duration((delay_ms * 900).micros());
+                        self.note_on(tone, melody.effect(progress));
+                        self.emit(Event::NoteOn {
+                            tone,
+                            progress,
+                            velocity: self.loudness(),
+                        });
+                        let duration_us = delay_ms * 100_000 / self.speed;
+                        self.timer.set_play_duration(duration_us.micros());
+                        self.timer
+                            .set_next_duration((duration_us / 10 * 9).micros());
+                        let now = self.timer.now();
+                        self.clock = Some(Clock {
+                            tone,
+                            note_ms: self.elapsed_ms,
+                            delay_ms,
+                            started: now,
+                            synced: (now, 0),
+                        });
+                        self.elapsed_ms += delay_ms;
                     } else {
-                        self.stop();
-                        self.start(pos, 0);
+                        self.finish(pos);
                     }
-                } else if next_fired {
+                } else if next_fired && self.clock.is_some() {
                     self.state = State::Play {
                         pos,
                         progress: progress + 1,
                     };
-                    self.buzzer.stop();
+                    self.note_off();
+                    self.emit(Event::NoteOff);
+                }
+
+                if tick_fired {
+                    self.handle_tick(melody);
                 }
             }
         }
     }
 
-    /// 上一曲下标，列表循环
-    fn get_prev_pos(&self) -> usize {
-        let max_pos = self.list.len() - 1;
-        let pos = match self.state {
-            State::Play { pos, .. } => pos,
-            State::Pause { pos, .. } => pos,
-            State::Stop => 0,
+    /// 节拍器：下一拍的比较值从上一拍累加，不受中断延迟影响，不会越打越慢
+    fn handle_beat(&mut self) {
+        self.timer.check_next();
+        self.timer.check_tick();
+        if !self.timer.check_play() {
+            return;
+        }
+        let Some(metronome) = &mut self.metronome else {
+            return;
+        };
+        let (beat, accent) = metronome.next_beat();
+        let beat_us = metronome.beat_us();
+        let tone = if accent { ACCENT_TONE } else { BEAT_TONE };
+        self.click(tone.freq());
+        self.timer.advance_play_duration(beat_us.micros());
+        self.emit(Event::Beat { beat, accent });
+    }
+
+    /// 控制节拍：按曲子时间推进鼓点轨道，鼓声与旋律分时共用蜂鸣器
+    fn handle_tick(&mut self, melody: &Melody) {
+        self.timer.set_tick_duration(TICK_DURATION);
+
+        if self.fade.is_some() && !self.fade_tick() {
+            return;
+        }
+
+        let Some(clock) = &self.clock else {
+            return;
+        };
+        let now_ms = clock.note_ms + self.note_offset_ms(clock);
+
+        let was_sounding = self.drums.is_sounding();
+        match self.drums.tick(melody, now_ms, &mut self.rand) {
+            Some(Sound::Freq(freq)) => self.buzzer.freq(freq, self.gain()),
+            Some(Sound::Sample(sample)) => self.buzzer.sample(sample, self.gain()),
+            Some(Sound::Hold) => self.buzzer.stream(),
+            None if was_sounding => self.restore_note(),
+            None => {
+                let elapsed_ms = self.note_elapsed_ms();
+                if let Some(voice) = &self.voice {
+                    self.buzzer.refill(voice, self.gain(), elapsed_ms);
+                }
+            }
+        }
+    }
+
+    fn note_on(&mut self, tone: Tone, effect: Effect) {
+        let voice = Voice::new(
+            tone.freq(),
+            self.last_freq,
+            self.envelope,
+            self.wave,
+            effect,
+        );
+        if tone != Tone::REST {
+            self.last_freq = tone.freq();
+        }
+        if !self.drums.is_sounding() {
+            self.buzzer.start(&voice, self.gain(), 0);
+        }
+        self.voice = Some(voice);
+    }
+
+    fn note_off(&mut self) {
+        let elapsed_ms = self.note_elapsed_ms();
+        let gain = self.gain();
+        if let Some(voice) = &mut self.voice {
+            voice.release(elapsed_ms);
+            if !self.drums.is_sounding() {
+                self.buzzer.update(voice, gain, elapsed_ms);
+            }
+        }
+    }
+
+    /// 鼓声结束后从包络和音高效果的当前位置恢复被打断的旋律音符
+    fn restore_note(&mut self) {
+        let elapsed_ms = self.note_elapsed_ms();
+        match &self.voice {
+            Some(voice) => self.buzzer.start(voice, self.gain(), elapsed_ms),
+            None => self.buzzer.stop(),
+        }
+    }
+
+    /// 当前音符已经走过的曲子时间，随播放速度缩放
+    fn note_offset_ms(&self, clock: &Clock) -> u32 {
+        let (at, offset_ms) = clock.synced;
+        offset_ms + Self::scale(self.speed, (self.timer.now() - at).to_millis())
+    }
+
+    /// 按播放速度把实际时间换算成曲子时间
+    fn scale(speed: u32, ms: u32) -> u32 {
+        ms * speed / 100
+    }
+
+    /// 当前音符实际已经发声的时间，包络和音高效果按实际时间推进
+    fn note_elapsed_ms(&self) -> u32 {
+        self.clock
+            .as_ref()
+            .map(|clock| (self.timer.now() - clock.started).to_millis())
+            .unwrap_or(0)
+    }
+
+    /// 下一个音符到达 B 点时返回 A 点
+    fn loop_restart(&self, pos: usize) -> Option<u32> {
+        if self.jingle.is_some() {
+            return None;
+        }
+        match self.loops.get(&pos) {
+            Some(&Loop {
+                a_ms,
+                b_ms: Some(b_ms),
+            }) if self.elapsed_ms >= b_ms => Some(a_ms),
+            _ => None,
+        }
+    }
+
+    /// 回到 A 点，按设置先提速、打预备拍
+    fn restart_loop(&mut self, pos: usize, melody: &Melody, a_ms: u32) {
+        let progress = melody.pos_at(a_ms);
+        self.buzzer.stop();
+        self.voice = None;
+        self.clock = None;
+        self.state = State::Play { pos, progress };
+        self.elapsed_ms = melody.time_at(progress);
+        self.drums.seek(melody, self.elapsed_ms);
+
+        if self.speed < 100 {
+            self.speed = (self.speed + self.loop_speed_step).min(100);
+        }
+        if self.count_in {
+            // 打满一小节
+            self.count_in_beats = melody.signature().beats;
+        }
+        self.timer.set_play_duration(TICK_DURATION);
+    }
+
+    /// 预备拍：一声短促的滴答，持续一拍
+    fn count_in_click(&mut self, melody: &Melody) {
+        self.click(COUNT_IN_HZ);
+        let beat_us = melody.beat_ms() * 100_000 / self.speed;
+        self.timer.set_play_duration(beat_us.micros());
+    }
+
+    /// 一声短促的滴答
+    fn click(&mut self, freq: u32) {
+        let voice = Voice::new(
+            freq,
+            freq,
+            Instrument::Pluck.envelope(),
+            Wave::Square,
+            Effect::None,
+        );
+        self.buzzer.start(&voice, self.gain(), 0);
+    }
+
+    /// 一首曲子播放结束，按播放模式决定接下来播放什么
+    fn finish(&mut self, pos: usize) {
+        if self.end_jingle() {
+            return;
+        }
+        if self.stop_after_song {
+            self.stop_after_song = false;
+            self.stop_now();
+            self.emit(Event::SongFinished(pos));
+            self.emit(Event::Stopped);
+            return;
+        }
+        let next_pos = match self.mode {
+            _ if !self.queue.is_empty() => self.pop_queue(),
+            PlayMode::RepeatOne => Some(pos),
+            PlayMode::RepeatAll => self.get_next_pos().ok(),
+            PlayMode::Shuffle => self.get_shuffle_pos().ok(),
+            PlayMode::Once => None,
         };
+        self.stop_now();
+        self.emit(Event::SongFinished(pos));
+        match next_pos {
+            Some(next_pos) => self.start_song(next_pos),
+            // 列表放完，摇一摇从第一首重新开始
+            None => self.emit(Event::Stopped),
+        }
+    }
+
+    fn pop_queue(&mut self) -> Option<usize> {
+        (!self.queue.is_empty()).then(|| self.queue.remove(0))
+    }
+
+    /// 随机选择本轮还没有播过的曲目，全部播过后开始新的一轮
+    fn get_shuffle_pos(&mut self) -> Result<usize, Error> {
+        if self.list.is_empty() {
+            return Err(Error::EmptyList);
+        }
+        let len = self.list.len().min(SHUFFLE_MAX);
+        let all = u32::MAX
+            .checked_shr((SHUFFLE_MAX - len) as u32)
+            .unwrap_or(0);
+        let pos = self.current_pos().unwrap_or(0);
+        let current = if pos < SHUFFLE_MAX { 1 << pos } else { 0 };
 
-        if pos == 0 {
-            max_pos
+        self.unplayed &= all & !current;
+        if self.unplayed == 0 {
+            // 新的一轮不以刚播完的曲子开头
+            self.unplayed = all & !current;
+            if self.unplayed == 0 {
+                return Ok(pos);
+            }
+        }
+
+        let mut nth = self.rand.below(self.unplayed.count_ones());
+        let mut bits = self.unplayed;
+        loop {
+            let next_pos = bits.trailing_zeros();
+            if nth == 0 {
+                self.unplayed &= !(1 << next_pos);
+                return Ok(next_pos as usize);
+            }
+            bits &= !(1 << next_pos);
+            nth -= 1;
+        }
+    }
+
+    /// 结束插播，回到插播前的状态，返回是否正在插播
+    fn end_jingle(&mut self) -> bool {
+        let Some(jingle) = self.jingle.take() else {
+            return false;
+        };
+        self.stop_now();
+        self.state = jingle.resume;
+        true
+    }
+
+    /// 第 `pos` 首曲子，插播时总是插播的旋律
+    fn melody(&self, pos: usize) -> Option<&'a Melody> {
+        match &self.jingle {
+            Some(jingle) => Some(jingle.melody),
+            None => self.list.get(pos),
+        }
+    }
+
+    /// 当前曲目下标，停止时没有当前曲子
+    fn current_pos(&self) -> Result<usize, Error> {
+        match self.state {
+            State::Play { pos, .. } | State::Pause { pos, .. } => Ok(pos),
+            State::Stop => Err(Error::Stopped),
+        }
+    }
+
+    fn check_song(&self, pos: usize) -> Result<(), Error> {
+        if self.list.is_empty() {
+            Err(Error::EmptyList)
+        } else if pos >= self.list.len() {
+            Err(Error::NoSuchSong(pos))
         } else {
-            pos - 1
+            Ok(())
+        }
+    }
+
+    /// 上一曲下标，列表循环
+    fn get_prev_pos(&self) -> Result<usize, Error> {
+        let len = self.list.len();
+        if len == 0 {
+            return Err(Error::EmptyList);
         }
+        let pos = self.current_pos().unwrap_or(0);
+        Ok((pos + len - 1) % len)
     }
 
     /// 获取下一曲下标，列表循环
-    fn get_next_pos(&self) -> usize {
-        let max_pos = self.list.len() - 1;
-        let pos = match self.state {
-            State::Play { pos, .. } => pos,
-            State::Pause { pos, .. } => pos,
-            State::Stop => 0,
+    fn get_next_pos(&self) -> Result<usize, Error> {
+        let len = self.list.len();
+        if len == 0 {
+            return Err(Error::EmptyList);
+        }
+        let pos = self.current_pos().unwrap_or(0);
+        Ok((pos + 1) % len)
+    }
+
+    /// 当前音量经曲线换算、再乘上淡入淡出电平后的增益
+    /// 听感上的响度（0-100）：音量乘以淡入淡出的进度
+    fn loudness(&self) -> u32 {
+        let level = self.fade.as_ref().map_or(1000, Fade::level);
+        self.volume * level / 1000
+    }
+
+    fn gain(&self) -> u32 {
+        let level = self.fade.as_ref().map_or(1000, Fade::level);
+        self.curve.gain(self.volume) * level / 1000
+    }
+
+    /// 从当前电平开始淡入或淡出到 `to`，结束后执行 `after`
+    fn fade_to(&mut self, to: u32, duration_ms: u32, after: After) {
+        let from = self.fade.as_ref().map_or(1000, Fade::level);
+        self.fade = Some(Fade {
+            from,
+            to,
+            duration_ms,
+            elapsed_ms: 0,
+            after,
+        });
+        if duration_ms == 0 || !self.is_playing() {
+            self.finish_fade();
+        }
+    }
+
+    /// 从静音开始淡入
+    fn fade_in(&mut self, duration_ms: u32) {
+        self.fade = (duration_ms > 0).then_some(Fade {
+            from: 0,
+            to: 1000,
+            duration_ms,
+            elapsed_ms: 0,
+            after: After::Nothing,
+        });
+    }
+
+    /// 推进 1ms 淡入淡出，返回本次节拍是否还要继续处理曲子
+    fn fade_tick(&mut self) -> bool {
+        let Some(fade) = &mut self.fade else {
+            return true;
         };
+        // 淡入等第一个音符响起后才开始
+        if fade.to > fade.from && self.clock.is_none() {
+            return true;
+        }
+        fade.elapsed_ms += 1;
+        if fade.elapsed_ms >= fade.duration_ms {
+            return self.finish_fade();
+        }
+        if fade.elapsed_ms.is_multiple_of(FADE_STEP_MS) && !self.drums.is_sounding() {
+            let elapsed_ms = self.note_elapsed_ms();
+            let gain = self.gain();
+            if let Some(voice) = &self.voice {
+                self.buzzer.update(voice, gain, elapsed_ms);
+            }
+        }
+        true
+    }
 
-        if pos == max_pos {
-            0
+    /// 淡入淡出结束，返回曲子是否还在继续播放
+    fn finish_fade(&mut self) -> bool {
+        let Some(fade) = self.fade.take() else {
+            return true;
+        };
+        match fade.after {
+            After::Nothing => true,
+            After::Pause => {
+                if let State::Play { pos, progress } = self.state {
+                    self.stop_now();
+                    self.state = State::Pause { pos, progress };
+                    self.emit(Event::Paused);
+                }
+                false
+            }
+            After::Stop => {
+                self.stop_now();
+                self.emit(Event::Stopped);
+                false
+            }
+            After::Start(pos) => {
+                self.stop_now();
+                self.start_song(pos);
+                self.fade_in(self.song_fade_ms);
+                false
+            }
+        }
+    }
+
+    /// 切歌：播放中先短暂淡出，再淡入新的曲子
+    fn change_song(&mut self, pos: usize) {
+        if self.is_playing() {
+            self.fade_to(0, self.song_fade_ms, After::Start(pos));
         } else {
-            pos + 1
+            self.stop_now();
+            self.start_song(pos);
+        }
+    }
+
+    /// 从头播放第 `pos` 首曲子
+    fn start_song(&mut self, pos: usize) {
+        self.start(pos, 0);
+        self.emit(Event::SongStarted(pos));
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(f) = self.attach_event_fn {
+            f(event);
         }
     }
 
     fn start(&mut self, pos: usize, progress: usize) {
+        self.metronome = None;
+        self.pitch = None;
         self.state = State::Play { pos, progress };
+        self.elapsed_ms = 0;
+        if let Some(melody) = self.melody(pos) {
+            self.envelope = melody.instrument().envelope();
+            self.wave = melody.wave();
+            self.elapsed_ms = melody.time_at(progress);
+            self.total_ms = melody.duration_ms();
+            self.drums.seek(melody, self.elapsed_ms);
+        }
         self.timer.start();
         self.timer.set_play_duration(DEFAULT_PLAY_DURATION);
+        self.timer.set_tick_duration(TICK_DURATION);
     }
 
-    fn stop(&mut self) {
+    fn stop_now(&mut self) {
+        self.pitch = None;
         self.timer.stop();
         self.buzzer.stop();
+        self.voice = None;
+        self.clock = None;
+        self.count_in_beats = 0;
+        self.fade = None;
         self.state = State::Stop;
     }
 }
@@ -174,47 +1348,404 @@ impl<'a, T: timer::Instance, P: pwm::Instance> Player<'a, T, P> {
 mod inner {
     use super::*;
 
-    pub(super) struct PlayerBuzzer<T: pwm::Instance>(pwm::Pwm<T>);
+    use core::ptr::addr_of_mut;
+    use core::sync::atomic::{compiler_fence, Ordering};
+
+    /// 每段最多的 DMA 采样数
+    const SEQ_LEN: usize = 128;
+    /// 采样的目标间隔
+    const STEP_US: u32 = 2000;
+    /// 距离已渲染段结束不足该时间时续写下一段
+    const REFILL_MARGIN_MS: u32 = 20;
+
+    /// WaveForm 模式的一个采样：通道 0-2 的比较值和 COUNTERTOP
+    type Step = [u16; 4];
+
+    /// 两块交替使用的 EasyDMA 序列缓冲，续写时不会改写正在读取的缓冲
+    static mut SEQ_BUFFER: [[Step; SEQ_LEN]; 2] = [[[0; 4]; SEQ_LEN]; 2];
+
+    /// DAC 模式的 COUNTERTOP：16MHz / (2 * 125) = 64kHz 载波，远高于可听范围
+    const DAC_TOP: u16 = 125;
+    const DAC_CARRIER_HZ: u32 = 64_000;
+    /// 每块 DAC 缓冲的采样数
+    const DAC_CHUNK: usize = 256;
+    /// 波表合成的采样率（每块缓冲 16ms）
+    const WAVE_RATE: u32 = 16_000;
+    /// 波表合成时每隔多少个采样重新计算一次音高和电平（2ms）
+    const WAVE_CONTROL_SAMPLES: usize = 32;
+
+    /// DAC 双缓冲：seq0 与 seq1 交替播放，播完的一块在下一次续写时填充
+    static mut DAC_BUFFER: [[u16; DAC_CHUNK]; 2] = [[0; DAC_CHUNK]; 2];
+
+    /// 把 PWM 当作 DAC 时的采样来源
+    enum Source {
+        /// flash 中的 PCM 采样
+        Sample { data: &'static [u8], pos: usize },
+        /// 波表振荡器，`next_ms` 为下一块缓冲开始时的音符时间
+        Wave {
+            table: &'static Wavetable,
+            osc: Oscillator,
+            next_ms: u32,
+        },
+    }
+
+    /// 正在进行的 DAC 输出
+    struct Stream {
+        source: Source,
+        gain: u32,
+        /// 采样用完后已经播完的静音缓冲数，两块都播完后停止
+        drained: u8,
+    }
+
+    impl Stream {
+        fn rate(&self) -> u32 {
+            match self.source {
+                Source::Sample { .. } => SAMPLE_RATE,
+                Source::Wave { .. } => WAVE_RATE,
+            }
+        }
+
+        fn is_exhausted(&self) -> bool {
+            match self.source {
+                Source::Sample { data, pos } => pos >= data.len(),
+                Source::Wave { .. } => false,
+            }
+        }
+
+        /// 填满缓冲；波表按 `voice` 的音高效果和包络合成，采样用完后填静音（占空比 50%）
+        fn fill(&mut self, buffer: &mut [u16; DAC_CHUNK], voice: Option<&Voice>) {
+            let top = DAC_TOP as i32;
+            let gain = self.gain.min(GAIN_MAX) as i32;
+            let to_duty = |value: i32| ((128 + value) * top / 256) as u16 & 0x7FFF;
+
+            match &mut self.source {
+                Source::Sample { data, pos } => {
+                    for duty in buffer.iter_mut() {
+                        let value = data.get(*pos).copied().unwrap_or(128) as i32 - 128;
+                        *pos = (*pos + 1).min(data.len());
+                        // 以中点为基准按增益缩放振幅
+                        *duty = to_duty(value * gain / GAIN_MAX as i32);
+                    }
+                }
+                Source::Wave {
+                    table,
+                    osc,
+                    next_ms,
+                } => {
+                    let Some(voice) = voice else {
+                        buffer.fill(to_duty(0));
+                        return;
+                    };
+                    for (i, block) in buffer.chunks_mut(WAVE_CONTROL_SAMPLES).enumerate() {
+                        let ms = *next_ms + (i * WAVE_CONTROL_SAMPLES) as u32 * 1000 / WAVE_RATE;
+                        let level = voice.level_at(ms) as i32;
+                        osc.set_freq(voice.freq_at(ms), WAVE_RATE);
+                        for duty in block.iter_mut() {
+                            let value = osc.next(table) as i32;
+                            *duty = to_duty(value * level / 100 * gain / GAIN_MAX as i32);
+                        }
+                    }
+                    *next_ms += DAC_CHUNK as u32 * 1000 / WAVE_RATE;
+                }
+            }
+        }
+    }
+
+    /// 蜂鸣器：PWM 以 WaveForm 模式输出方波，每个采样同时携带占空比和周期，
+    /// 由 EasyDMA 在 PWM 周期边界装载，音量包络和音高效果变化时不会产生毛刺
+    pub(super) struct PlayerBuzzer<T: pwm::Instance> {
+        pwm: T,
+        /// 当前分频后的 PWM 时钟
+        clock_hz: u32,
+        buffer: usize,
+        sounding: bool,
+        /// 已渲染到的音符时间，`None` 表示输出已稳定无需续写
+        rendered_ms: Option<u32>,
+        stream: Option<Stream>,
+        calibration: Calibration,
+    }
 
     impl<T: pwm::Instance> PlayerBuzzer<T> {
         pub fn new(pwm: T, pin: Pin<Output<PushPull>>) -> Self {
-            let buzzer = pwm::Pwm::new(pwm);
-            buzzer
-                .set_counter_mode(pwm::CounterMode::UpAndDown)
-                .set_output_pin(pwm::Channel::C0, pin)
-                .disable();
-            Self(buzzer)
+            pwm.enable.write(|w| w.enable().disabled());
+            pwm.mode.write(|w| w.updown().up_and_down());
+            pwm.loop_.write(|w| w.cnt().disabled());
+            pwm.decoder.write(|w| {
+                w.load().wave_form();
+                w.mode().refresh_count()
+            });
+            pwm.seq0.enddelay.write(|w| unsafe { w.bits(0) });
+            pwm.psel.out[0].write(|w| {
+                unsafe { w.bits(pin.psel_bits()) };
+                w.connect().connected()
+            });
+            Self {
+                pwm,
+                clock_hz: 16_000_000,
+                buffer: 0,
+                sounding: false,
+                rendered_ms: None,
+                stream: None,
+                calibration: Calibration::DEFAULT,
+            }
+        }
+
+        pub fn calibration(&self) -> Calibration {
+            self.calibration
+        }
+
+        pub fn set_calibration(&mut self, calibration: Calibration) {
+            self.calibration = calibration;
+        }
+
+        /// 从音符时间 `ms` 开始发声（被鼓点打断后恢复时不为 0）
+        pub fn start(&mut self, voice: &Voice, gain: u32, ms: u32) {
+            self.stop();
+            let freq = voice.freq_at(ms);
+            if freq == 0 {
+                return;
+            }
+            if let Some(table) = voice.wave().table() {
+                let source = Source::Wave {
+                    table,
+                    osc: Oscillator::new(),
+                    next_ms: ms,
+                };
+                self.start_stream(source, gain, Some(voice));
+                return;
+            }
+            // 给向下的弯音和颤音留出余量
+            self.set_prescaler(freq * 3 / 4);
+            self.render(voice, gain, ms);
         }
 
-        pub fn tone(&self, tone: Tone, volume: u32) {
-            self.0.disable();
-            if tone != Tone::REST {
-                self.0.set_period(tone.hz());
-                self.set_volume(volume);
-                self.0.enable();
+        /// 从音符时间 `ms` 起重新渲染，用于松开等包络变化
+        pub fn update(&mut self, voice: &Voice, gain: u32, ms: u32) {
+            match &mut self.stream {
+                // 波表在每次续写时读取包络，只需更新增益
+                Some(stream) => stream.gain = gain,
+                None if self.sounding => self.render(voice, gain, ms),
+                None => {}
             }
         }
 
-        pub fn stop(&self) {
-            self.0.disable();
+        /// 音高或电平仍在变化时，在已渲染段播完前续写下一段
+        pub fn refill(&mut self, voice: &Voice, gain: u32, ms: u32) {
+            if self.stream.is_some() {
+                self.stream_with(Some(voice));
+            } else if let Some(rendered_ms) = self.rendered_ms {
+                if self.sounding && ms + REFILL_MARGIN_MS >= rendered_ms {
+                    self.render(voice, gain, ms);
+                }
+            }
         }
 
+        /// 以任意频率（Hz）、固定增益发声，频率为 0 时静音
+        pub fn freq(&mut self, freq: u32, gain: u32) {
+            self.pitch(freq * FREQ_SCALE, gain);
+        }
+
+        /// 持续的方波，`freq` 单位 1/`FREQ_SCALE` Hz
+        pub fn pitch(&mut self, freq: u32, gain: u32) {
+            if freq == 0 {
+                self.stop();
+                return;
+            }
+            if !self.sounding || self.clock_hz != Self::clock_for(freq) {
+                self.stop();
+                self.set_prescaler(freq);
+            }
+            let buffer = self.next_buffer();
+            buffer[0] = self.step(freq, gain, 100);
+            self.play(buffer, 1, 1);
+            self.rendered_ms = None;
+        }
+
+        pub fn stop(&mut self) {
+            self.pwm.enable.write(|w| w.enable().disabled());
+            self.sounding = false;
+            self.rendered_ms = None;
+            if self.stream.take().is_some() {
+                // 恢复方波使用的单序列 WaveForm 模式
+                self.pwm.shorts.reset();
+                self.pwm.loop_.write(|w| w.cnt().disabled());
+                self.pwm.decoder.write(|w| {
+                    w.load().wave_form();
+                    w.mode().refresh_count()
+                });
+            }
+        }
+
+        /// 播放 8 位 PCM 采样
+        pub fn sample(&mut self, sample: &'static Sample, gain: u32) {
+            self.stop();
+            let source = Source::Sample {
+                data: sample.data,
+                pos: 0,
+            };
+            self.start_stream(source, gain, None);
+        }
+
+        /// 续写已经播完的 PCM 缓冲；需要在一块缓冲播完之前（32ms）调用
+        pub fn stream(&mut self) {
+            self.stream_with(None);
+        }
+
+        /// 把 PWM 当作 DAC：占空比跟随采样值，载波被扬声器滤掉，
+        /// 两块缓冲由 EasyDMA 交替播放
+        fn start_stream(&mut self, source: Source, gain: u32, voice: Option<&Voice>) {
+            self.pwm
+                .prescaler
+                .write(|w| w.prescaler().bits(pwm::Prescaler::Div1.into()));
+            self.pwm
+                .countertop
+                .write(|w| unsafe { w.countertop().bits(DAC_TOP) });
+            self.pwm.decoder.write(|w| {
+                w.load().common();
+                w.mode().refresh_count()
+            });
+            // seq0 和 seq1 轮流播放，播完一轮后重新从 seq0 开始
+            self.pwm.loop_.write(|w| unsafe { w.cnt().bits(1) });
+            self.pwm.shorts.write(|w| w.loopsdone_seqstart0().enabled());
+
+            let mut stream = Stream {
+                source,
+                gain,
+                drained: 0,
+            };
+            // 每个采样保持的载波周期数
+            let refresh = DAC_CARRIER_HZ / stream.rate() - 1;
+            for (i, seq) in [&self.pwm.seq0, &self.pwm.seq1].into_iter().enumerate() {
+                // SAFETY: PWM 未启用，DMA 尚未读取该缓冲
+                let buffer = unsafe { &mut (*addr_of_mut!(DAC_BUFFER))[i] };
+                stream.fill(buffer, voice);
+                seq.ptr.write(|w| unsafe { w.bits(buffer.as_ptr() as u32) });
+                seq.cnt.write(|w| unsafe { w.bits(DAC_CHUNK as u32) });
+                seq.refresh.write(|w| unsafe { w.bits(refresh) });
+                seq.enddelay.write(|w| unsafe { w.bits(0) });
+            }
+            self.pwm.events_seqend[0].reset();
+            self.pwm.events_seqend[1].reset();
+
+            compiler_fence(Ordering::SeqCst);
+            self.pwm.enable.write(|w| w.enable().enabled());
+            self.pwm.tasks_seqstart[0].write(|w| unsafe { w.bits(1) });
+            self.sounding = true;
+            self.stream = Some(stream);
+        }
+
+        /// 续写已经播完的 DAC 缓冲
+        fn stream_with(&mut self, voice: Option<&Voice>) {
+            let Some(stream) = &mut self.stream else {
+                return;
+            };
+
+            let mut finished = false;
+            for i in 0..2 {
+                let event = &self.pwm.events_seqend[i];
+                if event.read().bits() == 0 {
+                    continue;
+                }
+                event.reset();
+
+                if stream.is_exhausted() {
+                    stream.drained += 1;
+                    finished = stream.drained >= 2;
+                }
+                // SAFETY: 该缓冲刚播完，DMA 正在读取另一块
+                stream.fill(unsafe { &mut (*addr_of_mut!(DAC_BUFFER))[i] }, voice);
+            }
+
+            if finished {
+                self.stop();
+            }
+        }
+
+        /// 把从 `ms` 开始的一段输出渲染成采样序列并播放，序列结束后 PWM 保持最后一个采样
+        fn render(&mut self, voice: &Voice, gain: u32, ms: u32) {
+            let period_us = (1_000_000 * FREQ_SCALE / voice.freq_at(ms).max(1)).max(1);
+            // 每个采样保持的 PWM 周期数
+            let periods = (STEP_US / period_us).max(1);
+            let step_us = periods * period_us;
+
+            let buffer = self.next_buffer();
+            let mut t_us = ms * 1000;
+            let mut steps = 0;
+            let mut settled = false;
+            while steps < SEQ_LEN && !settled {
+                let t_ms = t_us / 1000;
+                buffer[steps] = self.step(voice.freq_at(t_ms), gain, voice.level_at(t_ms));
+                settled = voice.settled_at(t_ms);
+                steps += 1;
+                t_us += step_us;
+            }
+
+            self.play(buffer, steps, periods);
+            self.rendered_ms = if settled { None } else { Some(t_us / 1000) };
+        }
+
+        /// 切换到另一块序列缓冲
+        fn next_buffer(&mut self) -> &'static mut [Step; SEQ_LEN] {
+            self.buffer ^= 1;
+            // SAFETY: 只有 PlayerBuzzer 访问该缓冲，且 DMA 正在读取的是另一块
+            unsafe { &mut (*addr_of_mut!(SEQ_BUFFER))[self.buffer] }
+        }
+
+        /// 播放序列的前 `steps` 个采样，每个采样保持 `periods` 个 PWM 周期
+        fn play(&mut self, buffer: &[Step], steps: usize, periods: u32) {
+            self.pwm
+                .seq0
+                .ptr
+                .write(|w| unsafe { w.bits(buffer.as_ptr() as u32) });
+            self.pwm
+                .seq0
+                .cnt
+                .write(|w| unsafe { w.bits(steps as u32 * 4) });
+            self.pwm
+                .seq0
+                .refresh
+                .write(|w| unsafe { w.bits(periods - 1) });
+
+            compiler_fence(Ordering::SeqCst);
+            self.pwm.enable.write(|w| w.enable().enabled());
+            self.pwm.tasks_seqstart[0].write(|w| unsafe { w.bits(1) });
+            self.sounding = true;
+        }
+
+        /// 频率（1/`FREQ_SCALE` Hz）、增益 0-`GAIN_MAX`、包络电平 0-100 对应的采样
         #[inline(always)]
-        fn set_volume(&self, volume: u32) {
-            // 确保音量在0-100范围内
-            let volume = volume.clamp(0, 100) as f32;
+        fn step(&self, freq: u32, gain: u32, level: u32) -> Step {
+            // UpAndDown 模式下一个周期计数两遍 COUNTERTOP
+            let top = (self.clock_hz / 2 * FREQ_SCALE / freq.max(1)).clamp(3, 32767);
 
-            // 获取最大占空比
-            let max_duty = self.0.max_duty() as f32;
+            // 按包络电平缩放增益，经校准表换算成占空比，最高位为 0 表示下降沿极性
+            let gain = gain.min(GAIN_MAX) * level.min(100) / 100;
+            let duty = self.calibration.duty(gain, top) as u16 & 0x7FFF;
+            [duty, 0, 0, top as u16]
+        }
+
+        fn set_prescaler(&mut self, freq: u32) {
+            let (prescaler, div) = Self::prescaler_for(freq);
+            self.pwm
+                .prescaler
+                .write(|w| w.prescaler().bits(prescaler.into()));
+            self.clock_hz = 16_000_000 / div;
+        }
 
-            // 计算目标占空比
-            let max_vol = max_duty * 0.5;
-            let min_vol = max_duty * 0.2;
-            let vol_range = max_vol - min_vol;
-            let target_duty = min_vol + (vol_range * (volume / 100.0));
+        fn clock_for(freq: u32) -> u32 {
+            16_000_000 / Self::prescaler_for(freq).1
+        }
 
-            // 设置PWM占空比
-            self.0.set_duty_on(pwm::Channel::C0, target_duty as u16);
+        /// 选择能容纳该频率周期的最小分频，避免低频（如底鼓）被 COUNTERTOP 截断
+        fn prescaler_for(freq: u32) -> (pwm::Prescaler, u32) {
+            // UpAndDown 模式下 COUNTERTOP = 16MHz / 分频 / freq / 2，最大 32767
+            match (16_000_000 / 2 * FREQ_SCALE / freq.max(1)) >> 15 {
+                0 => (pwm::Prescaler::Div1, 1),
+                1 => (pwm::Prescaler::Div2, 2),
+                2..=3 => (pwm::Prescaler::Div4, 4),
+                4..=7 => (pwm::Prescaler::Div8, 8),
+                _ => (pwm::Prescaler::Div16, 16),
+            }
         }
     }
 
@@ -229,6 +1760,7 @@ mod inner {
             timer0.prescaler.write(|w| unsafe { w.prescaler().bits(4) }); // 1 Mhz
             timer0.intenset.write(|w| w.compare1().set_bit());
             timer0.intenset.write(|w| w.compare2().set_bit());
+            timer0.intenset.write(|w| w.compare3().set_bit());
             Self(timer)
         }
 
@@ -247,10 +1779,22 @@ mod inner {
             self.set_duration_for_cc(1, duration)
         }
 
+        /// 下一次 play 事件在上一次的比较值之后 `duration` 触发，多次累加不漂移
+        pub fn advance_play_duration(&self, duration: Duration) {
+            let timer = self.0.as_timer0();
+            let last = timer.cc[1].read().bits();
+            let instant = Instant::from_ticks(last) + duration;
+            timer.cc[1].write(|w| unsafe { w.cc().bits(instant.duration_since_epoch().ticks()) });
+        }
+
         pub fn set_next_duration(&self, duration: Duration) {
             self.set_duration_for_cc(2, duration)
         }
 
+        pub fn set_tick_duration(&self, duration: Duration) {
+            self.set_duration_for_cc(3, duration)
+        }
+
         pub fn check_play(&self) -> bool {
             self.check_fired_for_cc(1)
         }
@@ -259,6 +1803,10 @@ mod inner {
             self.check_fired_for_cc(2)
         }
 
+        pub fn check_tick(&self) -> bool {
+            self.check_fired_for_cc(3)
+        }
+
         #[inline(always)]
         pub fn now(&self) -> Instant {
             let timer = self.0.as_timer0();
@@ -0,0 +1,27 @@
+/// xorshift32 伪随机数发生器，种子来自 nRF 的硬件 RNG
+pub struct Rand(u32);
+
+impl Rand {
+    pub fn new(seed: u32) -> Self {
+        // xorshift 的状态不能为 0
+        Self(if seed == 0 { 0x9E37_79B9 } else { seed })
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// 返回 `0..bound` 范围内的随机数，`bound` 为 0 时返回 0
+    pub fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            0
+        } else {
+            self.next_u32() % bound
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use bsp::hal::{pwm, timer};
+use fugit::TimerInstantU64;
+use heapless::Vec;
+use musicbox_core::rhythm;
+
+use crate::effect::Effect;
+use crate::melody::Melody;
+use crate::player::Player;
+use crate::tone::{self, Tone};
+use crate::tuner::NoteName;
+
+/// 一段录音最多的音符数（含休止）
+pub const RECORD_NOTES: usize = 64;
+/// 可选的音域，MIDI 音符号 C3-C7
+const NOTE_MIN: u8 = 48;
+const NOTE_MAX: u8 = 96;
+/// 开始时选中的音，C5
+const NOTE_START: u8 = 72;
+
+/// 录音在播放列表中的曲名
+const TAKE_NAME: &str = "RECORDING";
+/// 播放列表最多的曲子数，含录音
+const LIST_MAX: usize = 8;
+
+/// 录音机：按键按下到松开为一个音，松开到下一次按下为休止，
+/// 先记下毫秒数，结束时按所选速度吸附到最近的音符时值
+pub struct Recorder<const TIMER_HZ: u32> {
+    /// 选中的音，MIDI 音符号
+    note: u8,
+    recording: bool,
+    /// 录下的音及其毫秒数，休止为 `Tone::REST`
+    events: Vec<(Tone, u32), RECORD_NOTES>,
+    /// 正在按住的音及按下的时刻
+    pressed: Option<(Tone, TimerInstantU64<TIMER_HZ>)>,
+    /// 上一个音松开的时刻
+    released: Option<TimerInstantU64<TIMER_HZ>>,
+}
+
+impl<const TIMER_HZ: u32> Recorder<TIMER_HZ> {
+    pub fn new() -> Self {
+        Self {
+            note: NOTE_START,
+            recording: false,
+            events: Vec::new(),
+            pressed: None,
+            released: None,
+        }
+    }
+
+    /// 升高（正数）或降低（负数）若干半音，到音域边界为止
+    pub fn step(&mut self, semitones: i32) {
+        let note = (self.note as i32 + semitones).clamp(NOTE_MIN as i32, NOTE_MAX as i32);
+        self.note = note as u8;
+    }
+
+    pub fn name(&self) -> NoteName {
+        NoteName::from_midi(self.note)
+    }
+
+    pub fn tone(&self) -> Tone {
+        tone::from_midi(self.note).unwrap_or(Tone::REST)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// 开始新的一段录音，之前录下的丢弃
+    pub fn start(&mut self) {
+        self.recording = true;
+        self.events.clear();
+        self.pressed = None;
+        self.released = None;
+    }
+
+    /// 放弃正在录的一段
+    pub fn stop(&mut self) {
+        self.recording = false;
+        self.events.clear();
+    }
+
+    /// 按下按键，返回要发出的音。第一个音之前的等待不计入
+    pub fn press(&mut self, now: &TimerInstantU64<TIMER_HZ>) -> Tone {
+        let tone = self.tone();
+        // 上一个音的松开事件丢了时，按现在松开，不丢掉那个音
+        self.release(now);
+        if self.recording {
+            if let Some(released) = self.released.take() {
+                self.push(Tone::REST, released, now);
+            }
+            self.pressed = Some((tone, *now));
+        }
+        tone
+    }
+
+    /// 松开按键
+    pub fn release(&mut self, now: &TimerInstantU64<TIMER_HZ>) {
+        if let Some((tone, pressed)) = self.pressed.take() {
+            self.push(tone, pressed, now);
+            self.released = Some(*now);
+        }
+    }
+
+    /// 结束录音，按每分钟 `bpm` 拍（四分音符为一拍）吸附成曲谱
+    pub fn finish(
+        &mut self,
+        now: &TimerInstantU64<TIMER_HZ>,
+        bpm: u32,
+    ) -> Vec<(Tone, i8, Effect), RECORD_NOTES> {
+        self.release(now);
+        self.recording = false;
+
+        let notes: Vec<(Tone, i8), RECORD_NOTES> = rhythm::quantize(&self.events, Tone::REST, bpm);
+        notes
+            .into_iter()
+            .map(|(tone, div)| (tone, div, Effect::None))
+            .collect()
+    }
+
+    fn push(
+        &mut self,
+        tone: Tone,
+        from: TimerInstantU64<TIMER_HZ>,
+        to: &TimerInstantU64<TIMER_HZ>,
+    ) {
+        let ms = to
+            .checked_duration_since(from)
+            .map_or(0, |duration| duration.to_millis() as u32);
+        if self.events.push((tone, ms)).is_err() {
+            defmt::warn!("recording full");
+        }
+    }
+}
+
+const BLANK: Melody = Melody::new("", 120, &[]);
+
+/// 录音和播放列表的两份缓冲，交替使用，改写的总是播放器没有引用的那份
+pub struct Takes {
+    notes: [[(Tone, i8, Effect); RECORD_NOTES]; 2],
+    lists: [[Melody; LIST_MAX]; 2],
+}
+
+impl Takes {
+    pub const fn new() -> Self {
+        Self {
+            notes: [[(Tone::REST, 4, Effect::None); RECORD_NOTES]; 2],
+            lists: [[BLANK; LIST_MAX], [BLANK; LIST_MAX]],
+        }
+    }
+}
+
+/// 在内置曲子之后加上最近一段录音的播放列表
+pub struct Playlist {
+    builtin: &'static [Melody],
+    takes: &'static mut Takes,
+    slot: usize,
+}
+
+impl Playlist {
+    pub fn new(builtin: &'static [Melody], takes: &'static mut Takes) -> Self {
+        Self {
+            builtin,
+            takes,
+            slot: 0,
+        }
+    }
+
+    /// 换上新的录音并把播放器切换到新列表，返回录音的下标。
+    /// 上一段录音的循环点和队列条目不跟着同名的新录音走
+    pub fn publish<T: timer::Instance, P: pwm::Instance>(
+        &mut self,
+        ply: &mut Player<'static, T, P>,
+        notes: &[(Tone, i8, Effect)],
+        bpm: u32,
+    ) -> usize {
+        self.slot ^= 1;
+        let len = notes.len().min(RECORD_NOTES);
+        let pos = self.builtin.len().min(LIST_MAX - 1);
+
+        let take = &mut self.takes.notes[self.slot];
+        take[..len].copy_from_slice(&notes[..len]);
+        let take: *const [(Tone, i8, Effect)] = &take[..len];
+        let list = &mut self.takes.lists[self.slot];
+        for (melody, builtin) in list.iter_mut().zip(&self.builtin[..pos]) {
+            *melody = builtin.clone();
+        }
+        // SAFETY: 这一份缓冲上次发布后，下一次发布的 `set_list` 已把播放器换到另一份，
+        // 而 `set_list` 保证返回后播放器不再引用旧列表，也不向外交出列表里的曲子，
+        // 所以这时没有别的引用指向这一份。下面交出的共享引用同样只由播放器持有，
+        // 要到它再次被 `set_list` 换下之后，也就是再下一次发布时才会改写
+        list[pos] = Melody::new(TAKE_NAME, bpm.max(1), unsafe { &*take });
+        let list: *const [Melody] = &list[..=pos];
+
+        // SAFETY: 同上，列表和其中的录音属于同一份缓冲
+        ply.set_list(unsafe { &*list });
+        ply.forget(pos);
+        pos
+    }
+}
@@ -0,0 +1,32 @@
+use defmt::{Format, Formatter};
+
+/// 固件中 PCM 采样的采样率，`build.rs` 转换 WAV 时重采样到该值
+pub const SAMPLE_RATE: u32 = 8000;
+
+/// 存放在 flash 中的 8 位无符号单声道 PCM 采样，由 `build.rs` 从 `assets/samples` 生成
+#[derive(Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub name: &'static str,
+    pub data: &'static [u8],
+}
+
+impl Sample {
+    pub fn duration_ms(&self) -> u32 {
+        self.data.len() as u32 * 1000 / SAMPLE_RATE
+    }
+}
+
+impl Format for Sample {
+    fn format(&self, fmt: Formatter) {
+        defmt::write!(fmt, "Sample({=str})", self.name)
+    }
+}
+
+#[allow(dead_code)]
+mod assets {
+    use super::Sample;
+
+    include!(concat!(env!("OUT_DIR"), "/samples.rs"));
+}
+
+pub use assets::*;
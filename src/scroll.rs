@@ -0,0 +1,78 @@
+use bsp::display::nonblocking::GreyscaleImage;
+use heapless::String;
+
+use crate::font;
+
+/// 滚动文字最多的字符数，多出的截掉
+pub const SCROLL_CHARS: usize = 48;
+/// 点阵的列数
+const COLUMNS: i32 = 5;
+/// 字符之间空一列
+const GAP: u32 = 1;
+
+/// 从右往左滚过点阵的一行文字：从右边进入，完全移出左边后结束。
+/// 没有字形的字符显示为空格
+pub struct Scroll {
+    text: String<SCROLL_CHARS>,
+    /// 已经移动的列数
+    offset: i32,
+    /// 文字的总列数
+    width: i32,
+}
+
+impl Scroll {
+    pub fn new(text: &str) -> Self {
+        let mut chars = String::new();
+        for c in text.chars() {
+            if chars.push(c).is_err() {
+                break;
+            }
+        }
+        let width = chars
+            .chars()
+            .map(|c| font::span(font::glyph(c)).1 + GAP)
+            .sum::<u32>();
+        Self {
+            text: chars,
+            offset: 0,
+            width: width as i32,
+        }
+    }
+
+    /// 向左移动一列，文字已全部移出时返回 `false`
+    pub fn advance(&mut self) -> bool {
+        self.offset += 1;
+        self.offset < self.width + COLUMNS
+    }
+
+    /// 当前的画面
+    pub fn frame(&self) -> GreyscaleImage {
+        let mut data = [[0; 5]; 5];
+        // 文字第一列在点阵上的位置
+        let mut x = COLUMNS - self.offset;
+        for c in self.text.chars() {
+            if x >= COLUMNS {
+                break;
+            }
+            let glyph = font::glyph(c);
+            let (start, width) = font::span(glyph);
+            if let Some(glyph) = glyph {
+                for col in 0..width {
+                    let Ok(screen) = usize::try_from(x + col as i32) else {
+                        continue;
+                    };
+                    if screen >= COLUMNS as usize {
+                        break;
+                    }
+                    for (row, bits) in data.iter_mut().zip(glyph) {
+                        if bits & (0b10000 >> (start + col)) != 0 {
+                            row[screen] = 9;
+                        }
+                    }
+                }
+            }
+            x += (width + GAP) as i32;
+        }
+        GreyscaleImage::new(&data)
+    }
+}
@@ -0,0 +1,313 @@
+use bsp::hal::uarte;
+use bsp::pac::UART0;
+use core::str::{FromStr, SplitWhitespace};
+
+use defmt::Format;
+use heapless::{Deque, String, Vec};
+
+use crate::alarm::Alarm;
+use crate::clock::Time;
+use crate::volume::Curve;
+
+/// 一行命令的最大长度
+const LINE_LEN: usize = 32;
+/// 一次回复的最大长度
+pub const REPLY_LEN: usize = 256;
+/// 待发送字节的缓冲，放不下的字节丢弃
+const TX_LEN: usize = 512;
+
+/// 在锁外格式化好的回复，每行以 `\n` 结尾
+pub type Reply = String<REPLY_LEN>;
+
+/// 串口命令，曲目用播放列表下标（从 0 开始）表示
+#[derive(Format, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `queue <n>`：把第 n 首加到队列末尾
+    Queue(usize),
+    /// `next <n>`：把第 n 首插到队列最前面，下一首播放
+    PlayNext(usize),
+    /// `remove <i>`：移除队列中第 i 项
+    Remove(usize),
+    /// `clear`：清空队列
+    Clear,
+    /// `list`：打印队列
+    List,
+    /// `volume <0-100>`：按 1 档精确设置音量
+    Volume(u32),
+    /// `seek <±s>`：当前曲子快进（正数）或快退（负数）若干秒
+    Seek(i32),
+    /// `cal [<point> <duty>]`：把增益 point * 10% 的占空比（千分比）设为 duty 并保存，不带参数时打印校准表
+    Calibrate(Option<(usize, u16)>),
+    /// `curve <linear|perceptual|cubic>`：选择音量曲线
+    Curve(Curve),
+    /// `time [hh:mm[:ss]]`：设置或打印当前时间
+    Time(Option<Time>),
+    /// `alarm <slot> <hh:mm> <n>`：每天到点渐强播放第 n 首；`alarm <slot> off`：取消
+    Alarm(usize, Option<Alarm>),
+    /// `alarms`：打印所有闹钟
+    Alarms,
+    /// `chime <on|off>`：整点报时开关
+    Chime(bool),
+    /// `say <text>`：在点阵上滚动显示一行文字
+    Say(#[defmt(Debug2Format)] String<LINE_LEN>),
+    /// `title`：滚动显示当前曲名
+    Title,
+    /// `scroll <ms>`：设置滚动速度，每移动一列的毫秒数
+    Scroll(u64),
+}
+
+/// 命令的用法，参数缺失或无效时回复给用户
+const USAGE: &[(&str, &str)] = &[
+    ("queue", "queue <n>"),
+    ("next", "next <n>"),
+    ("remove", "remove <i>"),
+    ("clear", "clear"),
+    ("list", "list"),
+    ("volume", "volume <0-100>"),
+    ("seek", "seek <±s>"),
+    ("cal", "cal [<point> <duty>]"),
+    ("curve", "curve <linear|perceptual|cubic>"),
+    ("time", "time [hh:mm[:ss]]"),
+    ("alarm", "alarm <slot> <hh:mm> <n> | alarm <slot> off"),
+    ("alarms", "alarms"),
+    ("chime", "chime <on|off>"),
+    ("say", "say <text>"),
+    ("title", "title"),
+    ("scroll", "scroll <ms>"),
+];
+
+/// 解析失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseError {
+    /// 没有这个命令
+    Unknown,
+    /// 参数缺失、无效或多余，附带用法
+    Usage(&'static str),
+}
+
+/// 命令的参数，所有参数都经由这里取出并解析
+struct Args<'a>(SplitWhitespace<'a>);
+
+impl<'a> Args<'a> {
+    /// 必需参数，缺失或 `parse` 失败时为 `None`
+    fn next<T>(&mut self, parse: impl FnOnce(&'a str) -> Option<T>) -> Option<T> {
+        self.0.next().and_then(parse)
+    }
+
+    /// 可选参数，没有时为 `Some(None)`，有但 `parse` 失败时为 `None`
+    fn optional<T>(&mut self, parse: impl FnOnce(&'a str) -> Option<T>) -> Option<Option<T>> {
+        match self.0.next() {
+            Some(word) => parse(word).map(Some),
+            None => Some(None),
+        }
+    }
+
+    /// 参数已全部取完
+    fn end(&mut self) -> Option<()> {
+        self.0.next().is_none().then_some(())
+    }
+}
+
+/// 数字参数
+fn number<T: FromStr>(word: &str) -> Option<T> {
+    word.parse().ok()
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, ParseError> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or(ParseError::Unknown)?;
+        let &(_, usage) = USAGE
+            .iter()
+            .find(|(command, _)| *command == name)
+            .ok_or(ParseError::Unknown)?;
+        if name == "say" {
+            let text = line
+                .trim_start()
+                .strip_prefix("say")
+                .unwrap_or_default()
+                .trim();
+            return Ok(Command::Say(String::from(text)));
+        }
+        Self::parse_args(name, Args(words)).ok_or(ParseError::Usage(usage))
+    }
+
+    fn parse_args(name: &str, mut args: Args) -> Option<Self> {
+        let command = match name {
+            "queue" => Command::Queue(args.next(number)?),
+            "next" => Command::PlayNext(args.next(number)?),
+            "remove" => Command::Remove(args.next(number)?),
+            "clear" => Command::Clear,
+            "list" => Command::List,
+            "volume" => Command::Volume(args.next(number)?),
+            "seek" => Command::Seek(args.next(number)?),
+            "cal" => Command::Calibrate(match args.optional(number)? {
+                Some(point) => Some((point, args.next(number)?)),
+                None => None,
+            }),
+            "curve" => Command::Curve(args.next(|word| match word {
+                "linear" => Some(Curve::Linear),
+                "perceptual" => Some(Curve::Perceptual),
+                "cubic" => Some(Curve::Cubic),
+                _ => None,
+            })?),
+            "time" => Command::Time(args.optional(Time::parse)?),
+            "alarm" => {
+                let slot = args.next(number)?;
+                let time = args.next(|word| match word {
+                    "off" => Some(None),
+                    time => Time::parse(time).map(Some),
+                })?;
+                let alarm = match time {
+                    Some(Time { hour, minute, .. }) => Some(Alarm {
+                        hour,
+                        minute,
+                        song: args.next(number)?,
+                    }),
+                    None => None,
+                };
+                Command::Alarm(slot, alarm)
+            }
+            "alarms" => Command::Alarms,
+            "chime" => Command::Chime(args.next(|word| match word {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            })?),
+            "title" => Command::Title,
+            "scroll" => Command::Scroll(args.next(number)?),
+            _ => return None,
+        };
+        args.end()?;
+        Some(command)
+    }
+}
+
+/// 调试器 USB 串口（115200 8N1）上的行命令，每收到一行解析一次。
+/// 发送不阻塞：字节先放进缓冲，由 UART0 中断逐个发出
+pub struct Serial {
+    uart: UART0,
+    line: Vec<u8, LINE_LEN>,
+    /// 当前行超长，丢弃到行尾
+    discarding: bool,
+    tx: Deque<u8, TX_LEN>,
+    /// 正在发送一个字节，发完后产生 TXDRDY 事件
+    sending: bool,
+    attach_command_fn: Option<fn(Command)>,
+}
+
+impl Serial {
+    pub fn new(uart: UART0, pins: uarte::Pins) -> Self {
+        uart.psel
+            .txd
+            .write(|w| unsafe { w.bits(pins.txd.psel_bits()) });
+        uart.psel
+            .rxd
+            .write(|w| unsafe { w.bits(pins.rxd.psel_bits()) });
+        uart.baudrate.write(|w| w.baudrate().baud115200());
+        uart.enable.write(|w| w.enable().enabled());
+        uart.events_rxdrdy.reset();
+        uart.events_txdrdy.reset();
+        uart.intenset
+            .write(|w| w.rxdrdy().set_bit().txdrdy().set_bit());
+        uart.tasks_startrx.write(|w| unsafe { w.bits(1) });
+        uart.tasks_starttx.write(|w| unsafe { w.bits(1) });
+        Self {
+            uart,
+            line: Vec::new(),
+            discarding: false,
+            tx: Deque::new(),
+            sending: false,
+            attach_command_fn: None,
+        }
+    }
+
+    pub fn attach_command(&mut self, f: fn(Command)) {
+        self.attach_command_fn = Some(f);
+    }
+
+    /// 在 UART0 中断中调用，取出收到的字节，发出下一个待发送的字节
+    pub fn handle_uart_event(&mut self) {
+        while self.uart.events_rxdrdy.read().bits() != 0 {
+            self.uart.events_rxdrdy.reset();
+            let byte = self.uart.rxd.read().rxd().bits();
+            match byte {
+                b'\r' | b'\n' => self.handle_line(),
+                // 超长的行整行丢弃，直到下一个换行
+                _ => {
+                    if !self.discarding && self.line.push(byte).is_err() {
+                        self.line.clear();
+                        self.discarding = true;
+                    }
+                }
+            }
+        }
+        if self.uart.events_txdrdy.read().bits() != 0 {
+            self.uart.events_txdrdy.reset();
+            self.sending = false;
+            self.send_next();
+        }
+    }
+
+    /// 发送回复，`\n` 换成 `\r\n`
+    pub fn write(&mut self, reply: &str) {
+        for line in reply.split_inclusive('\n') {
+            match line.strip_suffix('\n') {
+                Some(line) => {
+                    self.write_bytes(line.as_bytes());
+                    self.write_bytes(b"\r\n");
+                }
+                None => self.write_bytes(line.as_bytes()),
+            }
+        }
+    }
+
+    fn handle_line(&mut self) {
+        if self.discarding {
+            self.discarding = false;
+            self.write_bytes(b"line too long\r\n");
+            return;
+        }
+        if self.line.is_empty() {
+            return;
+        }
+        let command = core::str::from_utf8(&self.line)
+            .map_err(|_| ParseError::Unknown)
+            .and_then(Command::parse);
+        self.line.clear();
+
+        match command {
+            Ok(command) => {
+                if let Some(f) = self.attach_command_fn {
+                    f(command);
+                }
+            }
+            Err(ParseError::Unknown) => self.write_bytes(b"unknown command\r\n"),
+            Err(ParseError::Usage(usage)) => {
+                self.write_bytes(b"usage: ");
+                self.write_bytes(usage.as_bytes());
+                self.write_bytes(b"\r\n");
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.tx.push_back(byte).is_err() {
+                break;
+            }
+        }
+        self.send_next();
+    }
+
+    /// 空闲时发出缓冲中的下一个字节
+    fn send_next(&mut self) {
+        if self.sending {
+            return;
+        }
+        if let Some(byte) = self.tx.pop_front() {
+            self.uart.txd.write(|w| unsafe { w.txd().bits(byte) });
+            self.sending = true;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use defmt::Format;
+
+use crate::metronome::Signature;
+use crate::player::{Loop, PlayMode, LOOP_SLOTS};
+use crate::volume::Curve;
+
+/// 设置记录的版本，字段含义改变时加一，旧版本的记录不再读取
+const VERSION: u32 = 2;
+/// 除循环点以外的字数
+const FIELD_WORDS: usize = 8;
+/// 保存到 flash 的字数，每个循环点占两个字
+pub const SETTINGS_WORDS: usize = FIELD_WORDS + LOOP_SLOTS * 2;
+/// 没有当前曲子
+const NO_SONG: u32 = u32::MAX;
+/// 空的循环点，或没有 B 点
+const NO_LOOP: u32 = u32::MAX;
+/// 循环 A 点占低 24 位（约 4.6 小时），高 8 位为曲子下标
+const A_MS_MASK: u32 = 0xff_ffff;
+
+const CURVES: [Curve; 3] = [Curve::Linear, Curve::Perceptual, Curve::Cubic];
+const MODES: [PlayMode; 4] = [
+    PlayMode::RepeatOne,
+    PlayMode::RepeatAll,
+    PlayMode::Shuffle,
+    PlayMode::Once,
+];
+
+/// 断电后要恢复的设置和播放位置
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// 音量，静音时为静音前的音量
+    pub volume: u32,
+    pub muted: bool,
+    pub curve: Curve,
+    pub mode: PlayMode,
+    /// 播放速度，百分比
+    pub speed: u32,
+    /// 当前曲子的下标和音符位置，停止时为空
+    pub song: Option<(usize, usize)>,
+    /// 节拍器的速度和拍号
+    pub bpm: u32,
+    pub signature: Signature,
+    /// 音叉的标准音
+    pub a4_hz: u32,
+    /// 各曲子的 A-B 循环点
+    pub loops: [Option<(usize, Loop)>; LOOP_SLOTS],
+}
+
+impl Settings {
+    pub fn to_words(self) -> [u32; SETTINGS_WORDS] {
+        let (pos, progress) = self.song.map_or((NO_SONG, 0), |(pos, progress)| {
+            (pos as u32, progress as u32)
+        });
+        let mut words = [NO_LOOP; SETTINGS_WORDS];
+        words[..FIELD_WORDS].copy_from_slice(&[
+            VERSION,
+            self.volume
+                | (self.muted as u32) << 8
+                | index(&CURVES, self.curve) << 16
+                | index(&MODES, self.mode) << 24,
+            self.speed,
+            pos,
+            progress,
+            self.bpm,
+            self.signature.beats << 16 | self.signature.unit,
+            self.a4_hz,
+        ]);
+        let slots = words[FIELD_WORDS..].chunks_exact_mut(2);
+        for (slot, (pos, points)) in slots.zip(self.loops.iter().flatten()) {
+            slot[0] = (*pos as u32) << 24 | points.a_ms.min(A_MS_MASK);
+            slot[1] = points.b_ms.unwrap_or(NO_LOOP);
+        }
+        words
+    }
+
+    /// 从保存的字还原，版本不符时为空
+    pub fn from_words(words: &[u32; SETTINGS_WORDS]) -> Option<Self> {
+        if words[0] != VERSION {
+            return None;
+        }
+        let flags = words[1];
+        let mut loops = [None; LOOP_SLOTS];
+        let slots = words[FIELD_WORDS..].chunks_exact(2);
+        for (points, slot) in loops.iter_mut().zip(slots) {
+            if slot[0] != NO_LOOP {
+                let a_ms = slot[0] & A_MS_MASK;
+                let b_ms = (slot[1] != NO_LOOP).then_some(slot[1]);
+                *points = Some(((slot[0] >> 24) as usize, Loop { a_ms, b_ms }));
+            }
+        }
+        Some(Self {
+            volume: (flags & 0xff).min(100),
+            muted: flags >> 8 & 1 != 0,
+            curve: *CURVES.get((flags >> 16 & 0xff) as usize)?,
+            mode: *MODES.get((flags >> 24) as usize)?,
+            speed: words[2],
+            song: (words[3] != NO_SONG).then_some((words[3] as usize, words[4] as usize)),
+            bpm: words[5],
+            signature: Signature {
+                beats: words[6] >> 16,
+                unit: words[6] & 0xffff,
+            },
+            a4_hz: words[7],
+            loops,
+        })
+    }
+}
+
+fn index<T: PartialEq>(items: &[T], item: T) -> u32 {
+    items.iter().position(|i| *i == item).unwrap_or(0) as u32
+}
@@ -0,0 +1,78 @@
+use bsp::pac;
+use defmt::Format;
+use fugit::{ExtU64, TimerInstantU64};
+
+/// 按键 A、B 所在的 P0 引脚，System OFF 后按下即唤醒（复位）
+const WAKE_PINS: [usize; 2] = [14, 23];
+
+/// 睡眠定时的档位
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sleep {
+    Off,
+    Minutes(u32),
+    /// 当前曲子播完后停止
+    EndOfSong,
+}
+
+impl Sleep {
+    /// 关闭 -> 15 -> 30 -> 60 分钟 -> 播完本曲 -> 关闭
+    pub fn next(self) -> Self {
+        match self {
+            Sleep::Off => Sleep::Minutes(15),
+            Sleep::Minutes(15) => Sleep::Minutes(30),
+            Sleep::Minutes(30) => Sleep::Minutes(60),
+            Sleep::Minutes(_) => Sleep::EndOfSong,
+            Sleep::EndOfSong => Sleep::Off,
+        }
+    }
+}
+
+pub struct SleepTimer<const TIMER_HZ: u32> {
+    setting: Sleep,
+    deadline: Option<TimerInstantU64<TIMER_HZ>>,
+}
+
+impl<const TIMER_HZ: u32> SleepTimer<TIMER_HZ> {
+    pub fn new() -> Self {
+        Self {
+            setting: Sleep::Off,
+            deadline: None,
+        }
+    }
+
+    pub fn setting(&self) -> Sleep {
+        self.setting
+    }
+
+    pub fn set(&mut self, setting: Sleep, now: &TimerInstantU64<TIMER_HZ>) {
+        self.setting = setting;
+        self.deadline = match setting {
+            Sleep::Minutes(minutes) => Some(*now + (minutes as u64 * 60).secs()),
+            _ => None,
+        };
+    }
+
+    /// 定时到点时返回 `true` 并关闭定时
+    pub fn expired(&mut self, now: &TimerInstantU64<TIMER_HZ>) -> bool {
+        match self.deadline {
+            Some(deadline) if *now >= deadline => {
+                self.set(Sleep::Off, now);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 进入 System OFF 低功耗模式，只保留按键唤醒，唤醒后芯片复位重新启动
+pub fn system_off(power: &pac::POWER) -> ! {
+    // SAFETY: 只修改按键引脚的 SENSE 位，输入和上拉配置保持不变
+    let p0 = unsafe { &*pac::P0::ptr() };
+    for pin in WAKE_PINS {
+        p0.pin_cnf[pin].modify(|_, w| w.sense().low());
+    }
+    power.systemoff.write(|w| w.systemoff().enter());
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
@@ -0,0 +1,239 @@
+use core::cell::UnsafeCell;
+use core::ptr::{read_volatile, write_volatile};
+
+use bsp::pac::NVMC;
+use defmt::Format;
+use heapless::Vec;
+
+/// nRF52833 的 flash 页大小
+const PAGE_SIZE: usize = 4096;
+const PAGE_WORDS: usize = PAGE_SIZE / 4;
+/// 每条记录开头的标记，最后一个字节为记录格式的版本
+const MAGIC: u32 = 0x4d42_5832;
+/// 页头的标记，整理完最后写入，有它的页才有效
+const PAGE_MAGIC: u32 = 0x4d42_5850;
+/// 页头占的字数：标记和代数
+const HEADER: usize = 2;
+/// 标记、种类和字数、CRC 共占的字数
+const OVERHEAD: usize = 3;
+/// 单条记录最多的字数，整理页面时在 RAM 中暂存
+const RECORD_MAX: usize = 32;
+/// 擦除后的 flash 内容
+const ERASED: u32 = u32::MAX;
+
+/// 占满两页并按页对齐，擦写时不会碰到程序代码。
+/// 内容由 NVMC 在编译器看不到的地方改写，所以放在 `UnsafeCell` 里，只做易失读写
+#[repr(C, align(4096))]
+struct Pages(UnsafeCell<[[u32; PAGE_WORDS]; 2]>);
+
+// SAFETY: 只有 `Storage` 访问，`Storage` 本身由 RTIC 的资源锁保护
+unsafe impl Sync for Pages {}
+
+/// 保留给设置的两页 flash，烧录固件时为擦除状态（全 1）。
+/// 含 `UnsafeCell` 的静态变量默认放在 RAM，这里指定放进 flash 的只读数据段
+#[link_section = ".rodata.storage"]
+static PAGES: Pages = Pages(UnsafeCell::new([[ERASED; PAGE_WORDS]; 2]));
+
+/// 保存的记录种类，同一种类以最后写入的一条为准
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Alarms = 1,
+    Settings = 2,
+    /// 本板扬声器的占空比校准表
+    Calibration = 3,
+}
+
+impl Key {
+    const ALL: [Key; 3] = [Key::Alarms, Key::Settings, Key::Calibration];
+}
+
+/// 用 NVMC 直接擦写保留页，断电后设置仍在。
+/// 记录依次追加在当前页内（标记、种类和字数、数据、CRC32），写满后把各种类最新的一条
+/// 整理到另一页，写完再写页头，代数更新的一页生效。擦除的总是不在用的那页，
+/// 整理时断电也还有一份完整的记录
+pub struct Storage {
+    nvmc: NVMC,
+    /// 当前页
+    page: usize,
+    /// 当前页的代数，每整理一次加一
+    generation: u32,
+    /// 下一条记录写入的位置
+    end: usize,
+}
+
+impl Storage {
+    pub fn new(nvmc: NVMC) -> Self {
+        let mut storage = Self {
+            nvmc,
+            page: 0,
+            generation: 0,
+            end: HEADER,
+        };
+        let valid = |page| (read(page, 0) == PAGE_MAGIC).then(|| read(page, 1));
+        match (valid(0), valid(1)) {
+            (Some(a), Some(b)) if (b.wrapping_sub(a) as i32) > 0 => storage.open(1, b),
+            (Some(a), _) => storage.open(0, a),
+            (None, Some(b)) => storage.open(1, b),
+            // 两页都没有页头：第一次开机，或者还是旧格式的单页
+            (None, None) => {
+                storage.erase(0);
+                storage.commit(0, 0);
+            }
+        }
+        storage
+    }
+
+    /// 读出该种类最新的一条记录，没有保存过或长度不符时为空
+    pub fn load<const N: usize>(&self, key: Key) -> Option<[u32; N]> {
+        let (offset, len) = self.find(key)?;
+        if len != N {
+            return None;
+        }
+        let mut words = [0; N];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = self.read(offset + i);
+        }
+        Some(words)
+    }
+
+    /// 追加一条记录，页内放不下时先整理到另一页，整理时 CPU 暂停约 85ms
+    pub fn save(&mut self, key: Key, words: &[u32]) {
+        assert!(words.len() <= RECORD_MAX);
+        if self.is_free(self.end, words.len() + OVERHEAD) {
+            self.end = self.append(self.page, self.end, key, words);
+        } else {
+            self.compact(key, words);
+        }
+    }
+
+    fn open(&mut self, page: usize, generation: u32) {
+        self.page = page;
+        self.generation = generation;
+        self.end = self.scan(|_, _, _| {});
+    }
+
+    /// 依次检查当前页的记录，对 CRC 正确的记录调用 `f(种类, 数据位置, 字数)`，
+    /// 返回记录之后的位置
+    fn scan(&self, mut f: impl FnMut(u32, usize, usize)) -> usize {
+        let mut pos = HEADER;
+        while pos + OVERHEAD <= PAGE_WORDS && self.read(pos) == MAGIC {
+            let header = self.read(pos + 1);
+            let len = (header & 0xffff) as usize;
+            let next = pos + OVERHEAD + len;
+            if next > PAGE_WORDS {
+                break;
+            }
+            let crc = crc32((pos + 1..pos + 2 + len).map(|i| self.read(i)));
+            if crc == self.read(next - 1) {
+                f(header >> 16, pos + 2, len);
+            }
+            pos = next;
+        }
+        pos
+    }
+
+    fn find(&self, key: Key) -> Option<(usize, usize)> {
+        let mut found = None;
+        self.scan(|k, offset, len| {
+            if k == key as u32 {
+                found = Some((offset, len));
+            }
+        });
+        found
+    }
+
+    fn is_free(&self, pos: usize, len: usize) -> bool {
+        pos + len <= PAGE_WORDS && (pos..pos + len).all(|i| self.read(i) == ERASED)
+    }
+
+    /// 擦除另一页，写入新记录和其余各种类最新的一条，最后写页头切换过去
+    fn compact(&mut self, key: Key, words: &[u32]) {
+        let page = self.page ^ 1;
+        self.erase(page);
+
+        let mut end = self.append(page, HEADER, key, words);
+        for other in Key::ALL.into_iter().filter(|&other| other != key) {
+            if let Some((offset, len)) = self.find(other) {
+                let kept: Vec<u32, RECORD_MAX> =
+                    (offset..offset + len).map(|i| self.read(i)).collect();
+                end = self.append(page, end, other, &kept);
+            }
+        }
+
+        self.commit(page, self.generation.wrapping_add(1));
+        self.end = end;
+    }
+
+    /// 写入页头并切换到该页。代数先写，标记最后写，写到一半断电时页头无效
+    fn commit(&mut self, page: usize, generation: u32) {
+        self.nvmc.config.write(|w| w.wen().wen());
+        for (i, word) in [(1, generation), (0, PAGE_MAGIC)] {
+            // SAFETY: 保留页内按字对齐的地址，NVMC 处于写使能状态
+            unsafe { write_volatile(base(page).add(i), word) };
+            self.wait_ready();
+        }
+        self.nvmc.config.write(|w| w.wen().ren());
+        self.page = page;
+        self.generation = generation;
+    }
+
+    fn erase(&mut self, page: usize) {
+        self.nvmc.config.write(|w| w.wen().een());
+        self.nvmc
+            .erasepage()
+            .write(|w| unsafe { w.bits(base(page) as u32) });
+        self.wait_ready();
+        self.nvmc.config.write(|w| w.wen().ren());
+    }
+
+    /// 在第 `page` 页的 `pos` 写入一条记录，返回记录之后的位置
+    fn append(&mut self, page: usize, pos: usize, key: Key, words: &[u32]) -> usize {
+        let header = (key as u32) << 16 | words.len() as u32;
+        let crc = crc32(core::iter::once(header).chain(words.iter().copied()));
+
+        self.nvmc.config.write(|w| w.wen().wen());
+        let record = [MAGIC, header].into_iter().chain(words.iter().copied());
+        for (i, word) in record.chain([crc]).enumerate() {
+            // SAFETY: 保留页内按字对齐的地址，NVMC 处于写使能状态
+            unsafe { write_volatile(base(page).add(pos + i), word) };
+            self.wait_ready();
+        }
+        self.nvmc.config.write(|w| w.wen().ren());
+        pos + OVERHEAD + words.len()
+    }
+
+    fn read(&self, index: usize) -> u32 {
+        read(self.page, index)
+    }
+
+    fn wait_ready(&self) {
+        while self.nvmc.ready.read().ready().is_busy() {}
+    }
+}
+
+/// 第 `page` 页的起始地址
+fn base(page: usize) -> *mut u32 {
+    PAGES.0.get().cast::<u32>().wrapping_add(page * PAGE_WORDS)
+}
+
+/// 内容随时可能被 NVMC 改写，必须用易失读取
+fn read(page: usize, index: usize) -> u32 {
+    // SAFETY: `index` 在保留页之内
+    unsafe { read_volatile(base(page).add(index)) }
+}
+
+/// CRC-32（IEEE 802.3），按字的小端字节计算
+fn crc32(words: impl Iterator<Item = u32>) -> u32 {
+    let mut crc = u32::MAX;
+    for byte in words.flat_map(u32::to_le_bytes) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
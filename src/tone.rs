@@ -151,3 +151,27 @@ tones!(
     B8: 7902,
     B9: 15804
 );
+
+pub use musicbox_core::pitch::transpose;
+
+/// C1 的 MIDI 音符号
+const MIDI_C1: u8 = 24;
+
+#[rustfmt::skip]
+const OCTAVES: [[Tone; 12]; 9] = [
+    [Tone::C1, Tone::CS1, Tone::D1, Tone::DS1, Tone::E1, Tone::F1, Tone::FS1, Tone::G1, Tone::GS1, Tone::A1, Tone::AS1, Tone::B1],
+    [Tone::C2, Tone::CS2, Tone::D2, Tone::DS2, Tone::E2, Tone::F2, Tone::FS2, Tone::G2, Tone::GS2, Tone::A2, Tone::AS2, Tone::B2],
+    [Tone::C3, Tone::CS3, Tone::D3, Tone::DS3, Tone::E3, Tone::F3, Tone::FS3, Tone::G3, Tone::GS3, Tone::A3, Tone::AS3, Tone::B3],
+    [Tone::C4, Tone::CS4, Tone::D4, Tone::DS4, Tone::E4, Tone::F4, Tone::FS4, Tone::G4, Tone::GS4, Tone::A4, Tone::AS4, Tone::B4],
+    [Tone::C5, Tone::CS5, Tone::D5, Tone::DS5, Tone::E5, Tone::F5, Tone::FS5, Tone::G5, Tone::GS5, Tone::A5, Tone::AS5, Tone::B5],
+    [Tone::C6, Tone::CS6, Tone::D6, Tone::DS6, Tone::E6, Tone::F6, Tone::FS6, Tone::G6, Tone::GS6, Tone::A6, Tone::AS6, Tone::B6],
+    [Tone::C7, Tone::CS7, Tone::D7, Tone::DS7, Tone::E7, Tone::F7, Tone::FS7, Tone::G7, Tone::GS7, Tone::A7, Tone::AS7, Tone::B7],
+    [Tone::C8, Tone::CS8, Tone::D8, Tone::DS8, Tone::E8, Tone::F8, Tone::FS8, Tone::G8, Tone::GS8, Tone::A8, Tone::AS8, Tone::B8],
+    [Tone::C9, Tone::CS9, Tone::D9, Tone::DS9, Tone::E9, Tone::F9, Tone::FS9, Tone::G9, Tone::GS9, Tone::A9, Tone::AS9, Tone::B9],
+];
+
+/// MIDI 音符号对应的音，超出 C1-B9 时为 `None`
+pub fn from_midi(note: u8) -> Option<Tone> {
+    let index = note.checked_sub(MIDI_C1)? as usize;
+    OCTAVES.get(index / 12).map(|octave| octave[index % 12])
+}
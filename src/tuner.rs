@@ -0,0 +1,88 @@
+use defmt::Format;
+
+use crate::tone::transpose;
+use crate::voice::FREQ_SCALE;
+
+/// A4 标准音频率的可调范围
+pub const A4_MIN: u32 = 415;
+pub const A4_MAX: u32 = 466;
+/// 可选的音域，MIDI 音符号 C2-C7
+const NOTE_MIN: u8 = 36;
+const NOTE_MAX: u8 = 96;
+const A4_NOTE: u8 = 69;
+
+const NAMES: [(char, bool); 12] = [
+    ('C', false),
+    ('C', true),
+    ('D', false),
+    ('D', true),
+    ('E', false),
+    ('F', false),
+    ('F', true),
+    ('G', false),
+    ('G', true),
+    ('A', false),
+    ('A', true),
+    ('B', false),
+];
+
+/// 音名：字母、是否升半音、八度（中央 C 为 C4）
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteName {
+    pub letter: char,
+    pub sharp: bool,
+    pub octave: u8,
+}
+
+impl NoteName {
+    /// MIDI 音符号对应的音名
+    pub fn from_midi(note: u8) -> Self {
+        let (letter, sharp) = NAMES[note as usize % 12];
+        NoteName {
+            letter,
+            sharp,
+            octave: note / 12 - 1,
+        }
+    }
+}
+
+/// 音叉：按十二平均律从 A4 标准音算出所选音的频率
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TuningFork {
+    /// MIDI 音符号
+    note: u8,
+    a4_hz: u32,
+}
+
+impl TuningFork {
+    pub fn new() -> Self {
+        Self {
+            note: A4_NOTE,
+            a4_hz: 440,
+        }
+    }
+
+    /// 升高（正数）或降低（负数）若干半音，到音域边界为止
+    pub fn step(&mut self, semitones: i32) {
+        let note = (self.note as i32 + semitones).clamp(NOTE_MIN as i32, NOTE_MAX as i32);
+        self.note = note as u8;
+    }
+
+    pub fn a4_hz(&self) -> u32 {
+        self.a4_hz
+    }
+
+    pub fn set_a4_hz(&mut self, hz: u32) {
+        self.a4_hz = hz.clamp(A4_MIN, A4_MAX);
+    }
+
+    pub fn name(&self) -> NoteName {
+        NoteName::from_midi(self.note)
+    }
+
+    /// 所选音的频率，单位 1/`FREQ_SCALE` Hz
+    pub fn freq(&self) -> u32 {
+        let cents = (self.note as i32 - A4_NOTE as i32) * 100;
+        transpose(self.a4_hz * FREQ_SCALE, cents)
+    }
+}
@@ -0,0 +1,500 @@
+use bsp::display::nonblocking::{Display, GreyscaleImage};
+use bsp::hal::timer::Instance;
+use fugit::{ExtU64, TimerInstantU64};
+
+use crate::font::{self, Glyph};
+use crate::mode::Mode;
+use crate::player::{PlayMode, PlaybackState};
+use crate::scroll::Scroll;
+use crate::sleep::Sleep;
+use crate::theremin::Scale;
+use crate::tuner::NoteName;
+use crate::visualizer::{Style, Visualizer};
+
+/// 临时提示显示的时长
+const TOAST_MS: u64 = 1500;
+/// 轮流显示的每一页停留的时长
+const PAGE_MS: u64 = 700;
+/// 音量条显示的时长
+const VOLUME_MS: u64 = 1000;
+/// 滚动文字默认每隔多久移动一列
+const SCROLL_MS: u64 = 120;
+/// 可视化每一帧的时长
+const FRAME_MS: u64 = 80;
+
+/// 表盘外圈的 12 个点，从 12 点方向顺时针，每个点代表 5 分钟
+const DIAL: [(usize, usize); 12] = [
+    (0, 2),
+    (0, 3),
+    (1, 4),
+    (2, 4),
+    (3, 4),
+    (4, 3),
+    (4, 2),
+    (4, 1),
+    (3, 0),
+    (2, 0),
+    (1, 0),
+    (0, 1),
+];
+
+#[rustfmt::skip]
+const OFF: GreyscaleImage = GreyscaleImage::new(&[
+    [9, 0, 0, 0, 9],
+    [0, 9, 0, 9, 0],
+    [0, 0, 9, 0, 0],
+    [0, 9, 0, 9, 0],
+    [9, 0, 0, 0, 9],
+]);
+
+#[rustfmt::skip]
+const NOTE: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 9, 9, 0],
+    [0, 0, 9, 0, 9],
+    [0, 0, 9, 0, 0],
+    [9, 9, 9, 0, 0],
+    [9, 9, 9, 0, 0],
+]);
+
+/// 轮流显示的两页，以及当前页和翻页的时刻
+struct Pages<const TIMER_HZ: u32> {
+    images: [GreyscaleImage; 2],
+    index: usize,
+    turn_at: TimerInstantU64<TIMER_HZ>,
+}
+
+/// 正在滚动的文字，以及下一次移动的时刻
+struct Scrolling<const TIMER_HZ: u32> {
+    scroll: Scroll,
+    step_at: TimerInstantU64<TIMER_HZ>,
+}
+
+/// LED 点阵：显示一段时间后自动熄灭的提示，提示消失后回到滚动文字、音乐可视化或轮流显示的页面。
+/// 文字滚完、可视化暗下去之前页面不翻动
+pub struct Ui<const TIMER_HZ: u32> {
+    hide_at: Option<TimerInstantU64<TIMER_HZ>>,
+    pages: Option<Pages<TIMER_HZ>>,
+    scrolling: Option<Scrolling<TIMER_HZ>>,
+    /// 滚动文字每隔多少毫秒移动一列
+    scroll_ms: u64,
+    visualizer: Visualizer,
+    /// 可视化下一帧的时刻
+    frame_at: TimerInstantU64<TIMER_HZ>,
+}
+
+impl<const TIMER_HZ: u32> Ui<TIMER_HZ> {
+    pub fn new() -> Self {
+        Self {
+            hide_at: None,
+            pages: None,
+            scrolling: None,
+            scroll_ms: SCROLL_MS,
+            visualizer: Visualizer::new(),
+            frame_at: TimerInstantU64::from_ticks(0),
+        }
+    }
+
+    pub fn visual_style(&self) -> Style {
+        self.visualizer.style()
+    }
+
+    pub fn set_visual_style<T: Instance>(&mut self, display: &mut Display<T>, style: Style) {
+        self.visualizer.set_style(style);
+        if self.hide_at.is_none() {
+            self.show(display);
+        }
+    }
+
+    /// 音符开始，交给可视化，参数见 `Visualizer::note_on`
+    pub fn note_on<T: Instance>(
+        &mut self,
+        display: &mut Display<T>,
+        freq: u32,
+        velocity: u32,
+        beat: Option<bool>,
+    ) {
+        self.visualizer.note_on(freq, velocity, beat);
+        if self.hide_at.is_none() && self.visualizer.is_active() {
+            self.show(display);
+        }
+    }
+
+    pub fn note_off(&mut self) {
+        self.visualizer.note_off();
+    }
+
+    /// 滚动显示一行文字，替换正在滚动的文字
+    pub fn scroll<T: Instance>(
+        &mut self,
+        display: &mut Display<T>,
+        text: &str,
+        now: &TimerInstantU64<TIMER_HZ>,
+    ) {
+        self.hide_at = None;
+        self.scrolling = Some(Scrolling {
+            scroll: Scroll::new(text),
+            step_at: *now + self.scroll_ms.millis(),
+        });
+        self.show(display);
+    }
+
+    pub fn scroll_ms(&self) -> u64 {
+        self.scroll_ms
+    }
+
+    /// 设置滚动速度：每移动一列的毫秒数
+    pub fn set_scroll_ms(&mut self, ms: u64) {
+        self.scroll_ms = ms.max(1);
+    }
+
+    /// 两页轮流显示，直到 `clear_pages`
+    pub fn alternate<T: Instance>(
+        &mut self,
+        display: &mut Display<T>,
+        images: [GreyscaleImage; 2],
+        now: &TimerInstantU64<TIMER_HZ>,
+    ) {
+        self.hide_at = None;
+        self.pages = Some(Pages {
+            images,
+            index: 0,
+            turn_at: *now + PAGE_MS.millis(),
+        });
+        self.show(display);
+    }
+
+    /// 清除轮流显示的页面和滚动文字
+    pub fn clear_pages<T: Instance>(&mut self, display: &mut Display<T>) {
+        let shown = self.pages.take().is_some() | self.scrolling.take().is_some();
+        if shown && self.hide_at.is_none() {
+            display.clear();
+        }
+    }
+
+    pub fn toast<T: Instance>(
+        &mut self,
+        display: &mut Display<T>,
+        image: &GreyscaleImage,
+        now: &TimerInstantU64<TIMER_HZ>,
+    ) {
+        self.flash(display, image, now, TOAST_MS);
+    }
+
+    /// 短暂显示音量条
+    pub fn volume<T: Instance>(
+        &mut self,
+        display: &mut Display<T>,
+        volume: u32,
+        now: &TimerInstantU64<TIMER_HZ>,
+    ) {
+        self.flash(display, &volume_bar(volume), now, VOLUME_MS);
+    }
+
+    /// 显示 `ms` 毫秒后熄灭
+    pub fn flash<T: Instance>(
+        &mut self,
+        display: &mut Display<T>,
+        image: &GreyscaleImage,
+        now: &TimerInstantU64<TIMER_HZ>,
+        ms: u64,
+    ) {
+        display.show(image);
+        self.hide_at = Some(*now + ms.millis());
+    }
+
+    pub fn tick<T: Instance>(&mut self, display: &mut Display<T>, now: &TimerInstantU64<TIMER_HZ>) {
+        if let Some(hide_at) = self.hide_at {
+            if *now < hide_at {
+                return;
+            }
+            self.hide_at = None;
+            self.show(display);
+        }
+        if let Some(scrolling) = &mut self.scrolling {
+            if *now >= scrolling.step_at {
+                scrolling.step_at = *now + self.scroll_ms.millis();
+                if !scrolling.scroll.advance() {
+                    self.scrolling = None;
+                }
+                self.show(display);
+            }
+            return;
+        }
+        if self.visualizer.is_active() {
+            if *now >= self.frame_at {
+                self.frame_at = *now + FRAME_MS.millis();
+                self.visualizer.step();
+                self.show(display);
+            }
+            return;
+        }
+        if let Some(pages) = &mut self.pages {
+            if *now >= pages.turn_at {
+                pages.index ^= 1;
+                pages.turn_at = *now + PAGE_MS.millis();
+                display.show(&pages.images[pages.index]);
+            }
+        }
+    }
+
+    /// 依次显示滚动文字、可视化的当前画面或当前页，都没有时熄灭
+    fn show<T: Instance>(&self, display: &mut Display<T>) {
+        match (&self.scrolling, &self.pages) {
+            (Some(scrolling), _) => display.show(&scrolling.scroll.frame()),
+            _ if self.visualizer.is_active() => {
+                display.show(&GreyscaleImage::new(&self.visualizer.frame()))
+            }
+            (None, Some(pages)) => display.show(&pages.images[pages.index]),
+            (None, None) => display.clear(),
+        }
+    }
+}
+
+/// 表盘：点亮的外圈点数表示分钟数，其余外圈微亮
+pub fn dial(minutes: u32) -> GreyscaleImage {
+    let lit = minutes.div_ceil(5).min(12) as usize;
+    let mut data = [[0; 5]; 5];
+    for (i, &(row, col)) in DIAL.iter().enumerate() {
+        data[row][col] = if i < lit { 9 } else { 1 };
+    }
+    data[2][2] = 9;
+    GreyscaleImage::new(&data)
+}
+
+/// 睡眠定时档位的图标
+pub fn sleep(sleep: Sleep) -> GreyscaleImage {
+    match sleep {
+        Sleep::Off => OFF,
+        Sleep::Minutes(minutes) => dial(minutes),
+        Sleep::EndOfSong => NOTE,
+    }
+}
+
+/// 播放、暂停、停止的图标
+pub fn playback(state: PlaybackState) -> GreyscaleImage {
+    match state {
+        PlaybackState::Playing => PLAY,
+        PlaybackState::Paused => PAUSE,
+        PlaybackState::Stopped => STOP,
+    }
+}
+
+/// 曲目序号，从 1 开始，超过 9 时只显示个位
+pub fn track(pos: usize) -> GreyscaleImage {
+    let digit = char::from(b'0' + ((pos + 1) % 10) as u8);
+    GreyscaleImage::new(&glyph(font::glyph(digit)))
+}
+
+/// 音量条：从左到右每列 20 级，未满的列按比例从下往上点亮，底行微亮作刻度
+pub fn volume_bar(volume: u32) -> GreyscaleImage {
+    let volume = volume.min(100) as usize;
+    let mut data = [[0; 5]; 5];
+    data[4] = [1; 5];
+    for (col, top) in (0..5).map(|col| (col, volume.saturating_sub(col * 20).min(20))) {
+        for row in data.iter_mut().rev().take(top.div_ceil(4)) {
+            row[col] = 9;
+        }
+    }
+    GreyscaleImage::new(&data)
+}
+
+/// 播放模式的图标
+pub fn play_mode(mode: PlayMode) -> GreyscaleImage {
+    match mode {
+        PlayMode::RepeatOne => REPEAT_ONE,
+        PlayMode::RepeatAll => REPEAT_ALL,
+        PlayMode::Shuffle => SHUFFLE,
+        PlayMode::Once => ONCE,
+    }
+}
+
+/// 节拍：点亮小节中第几拍对应的一列，重拍最亮
+pub fn beat(beat: u32, accent: bool) -> GreyscaleImage {
+    let col = beat as usize % 5;
+    let level = if accent { 9 } else { 4 };
+    let mut data = [[0; 5]; 5];
+    for row in data.iter_mut() {
+        row[col] = level;
+    }
+    GreyscaleImage::new(&data)
+}
+
+/// 工作模式的图标
+pub fn mode(mode: Mode) -> GreyscaleImage {
+    match mode {
+        Mode::Player => NOTE,
+        Mode::Metronome => METRONOME,
+        Mode::Tuner => FORK,
+        Mode::Theremin => THEREMIN,
+        Mode::Recorder => RECORD,
+    }
+}
+
+/// 录音为空或被放弃的图标：垃圾桶
+pub fn discarded() -> GreyscaleImage {
+    DISCARD
+}
+
+/// 音阶的图标：连续为斜线，其余为台阶，台阶越密音阶里的音越多
+pub fn scale(scale: Scale) -> GreyscaleImage {
+    let steps = match scale {
+        Scale::Continuous => return RAMP,
+        Scale::Chromatic => 5,
+        Scale::Major => 3,
+        Scale::Minor => 3,
+        Scale::Pentatonic => 2,
+    };
+    let lit = if scale == Scale::Minor { 4 } else { 9 };
+    let mut data = [[0; 5]; 5];
+    for (row, levels) in data.iter_mut().enumerate() {
+        for (col, level) in levels.iter_mut().enumerate() {
+            let height = (col * steps / 5 + 1) * 5 / steps;
+            if row + height >= 5 {
+                *level = lit;
+            }
+        }
+    }
+    GreyscaleImage::new(&data)
+}
+
+/// 音名的两页：字母（升号在右上角）和八度数字
+pub fn note(name: NoteName) -> [GreyscaleImage; 2] {
+    let mut letter = glyph(font::glyph(name.letter));
+    if name.sharp {
+        letter[0][4] = 9;
+    }
+    let octave = glyph(font::glyph(char::from(b'0' + name.octave % 10)));
+    [GreyscaleImage::new(&letter), GreyscaleImage::new(&octave)]
+}
+
+/// 把字形展开成亮度
+fn glyph(glyph: Option<Glyph>) -> [[u8; 5]; 5] {
+    let mut data = [[0; 5]; 5];
+    if let Some(glyph) = glyph {
+        for (row, bits) in data.iter_mut().zip(glyph) {
+            for (col, level) in row.iter_mut().enumerate() {
+                if bits & (0b10000 >> col) != 0 {
+                    *level = 9;
+                }
+            }
+        }
+    }
+    data
+}
+
+#[rustfmt::skip]
+const PLAY: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 9, 0, 0, 0],
+    [0, 9, 9, 0, 0],
+    [0, 9, 9, 9, 0],
+    [0, 9, 9, 0, 0],
+    [0, 9, 0, 0, 0],
+]);
+
+#[rustfmt::skip]
+const PAUSE: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+]);
+
+#[rustfmt::skip]
+const STOP: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 0, 0, 0],
+    [0, 9, 9, 9, 0],
+    [0, 9, 9, 9, 0],
+    [0, 9, 9, 9, 0],
+    [0, 0, 0, 0, 0],
+]);
+
+#[rustfmt::skip]
+const REPEAT_ONE: GreyscaleImage = GreyscaleImage::new(&[
+    [9, 9, 9, 9, 0],
+    [9, 0, 9, 0, 9],
+    [9, 0, 9, 0, 9],
+    [9, 0, 9, 0, 9],
+    [0, 9, 9, 9, 9],
+]);
+
+#[rustfmt::skip]
+const REPEAT_ALL: GreyscaleImage = GreyscaleImage::new(&[
+    [9, 9, 9, 9, 0],
+    [9, 0, 0, 0, 9],
+    [9, 0, 0, 0, 9],
+    [9, 0, 0, 0, 9],
+    [0, 9, 9, 9, 9],
+]);
+
+#[rustfmt::skip]
+const SHUFFLE: GreyscaleImage = GreyscaleImage::new(&[
+    [9, 0, 0, 9, 9],
+    [0, 9, 0, 0, 9],
+    [0, 0, 9, 0, 0],
+    [0, 9, 0, 0, 9],
+    [9, 0, 0, 9, 9],
+]);
+
+#[rustfmt::skip]
+const ONCE: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 0, 0, 9],
+    [0, 0, 9, 0, 9],
+    [9, 9, 9, 9, 9],
+    [0, 0, 9, 0, 9],
+    [0, 0, 0, 0, 9],
+]);
+
+#[rustfmt::skip]
+const METRONOME: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 9, 0, 0],
+    [0, 0, 9, 0, 9],
+    [0, 9, 9, 9, 0],
+    [0, 9, 0, 9, 0],
+    [9, 9, 9, 9, 9],
+]);
+
+#[rustfmt::skip]
+const FORK: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+    [0, 0, 9, 0, 0],
+    [0, 0, 9, 0, 0],
+]);
+
+#[rustfmt::skip]
+const THEREMIN: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 0, 0, 9],
+    [0, 0, 0, 9, 0],
+    [9, 0, 9, 0, 0],
+    [0, 9, 0, 0, 0],
+    [0, 0, 0, 0, 0],
+]);
+
+#[rustfmt::skip]
+const RECORD: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 9, 9, 9, 0],
+    [9, 9, 9, 9, 9],
+    [9, 9, 9, 9, 9],
+    [9, 9, 9, 9, 9],
+    [0, 9, 9, 9, 0],
+]);
+
+#[rustfmt::skip]
+const DISCARD: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 9, 0, 0],
+    [9, 9, 9, 9, 9],
+    [0, 9, 0, 9, 0],
+    [0, 9, 0, 9, 0],
+    [0, 9, 9, 9, 0],
+]);
+
+#[rustfmt::skip]
+const RAMP: GreyscaleImage = GreyscaleImage::new(&[
+    [0, 0, 0, 0, 9],
+    [0, 0, 0, 9, 9],
+    [0, 0, 9, 9, 9],
+    [0, 9, 9, 9, 9],
+    [9, 9, 9, 9, 9],
+]);
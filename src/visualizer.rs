@@ -0,0 +1,167 @@
+use defmt::Format;
+
+/// 把音高分成 5 段的分界频率（Hz），C4 到 C6 之间每段约 5 个半音
+const BANDS_HZ: [u32; 4] = [345, 455, 600, 792];
+/// 音符停止后还要显示多少帧，之后让出点阵
+const IDLE_FRAMES: u32 = 20;
+const MAX_LEVEL: u8 = 9;
+
+/// 可视化样式
+#[derive(Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Off,
+    /// 音高对应列，低音在左
+    Columns,
+    /// 音高对应行，高音在上
+    Rows,
+    /// 随拍子从中心向外跳动
+    Pulse,
+    /// 卷帘：音符从右往左移动，音高对应行
+    Roll,
+}
+
+impl Style {
+    pub fn next(self) -> Self {
+        match self {
+            Style::Off => Style::Columns,
+            Style::Columns => Style::Rows,
+            Style::Rows => Style::Pulse,
+            Style::Pulse => Style::Roll,
+            Style::Roll => Style::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Off => "OFF",
+            Style::Columns => "COLUMNS",
+            Style::Rows => "ROWS",
+            Style::Pulse => "PULSE",
+            Style::Roll => "ROLL",
+        }
+    }
+}
+
+/// 音乐可视化：音符点亮对应音高的行或列，亮度取发声时的增益，拍子上闪一下，每帧逐渐变暗
+pub struct Visualizer {
+    style: Style,
+    data: [[u8; 5]; 5],
+    /// 正在发声的音所在的段和亮度，卷帘样式下持续画出
+    held: Option<(usize, u8)>,
+    /// 最后一个音符停止后过去的帧数
+    idle_frames: u32,
+}
+
+impl Visualizer {
+    pub fn new() -> Self {
+        Self {
+            style: Style::Off,
+            data: [[0; 5]; 5],
+            held: None,
+            idle_frames: IDLE_FRAMES,
+        }
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+        self.data = [[0; 5]; 5];
+        self.held = None;
+        self.idle_frames = IDLE_FRAMES;
+    }
+
+    /// 有音符正在显示或还没有完全暗下去
+    pub fn is_active(&self) -> bool {
+        self.style != Style::Off && (self.held.is_some() || self.idle_frames < IDLE_FRAMES)
+    }
+
+    /// 音符开始：`freq` 为频率（Hz），`velocity` 为听感上的响度（0-100），
+    /// `beat` 为是否落在拍上及是否为小节第一拍
+    pub fn note_on(&mut self, freq: u32, velocity: u32, beat: Option<bool>) {
+        if self.style == Style::Off {
+            return;
+        }
+        self.idle_frames = 0;
+        if freq == 0 {
+            self.held = None;
+            return;
+        }
+        let band = BANDS_HZ.iter().filter(|&&hz| freq >= hz).count();
+        let level = (velocity.min(100) * MAX_LEVEL as u32).div_ceil(100).max(1) as u8;
+        self.held = Some((band, level));
+
+        let pulse = beat.map(|accent| if accent { MAX_LEVEL } else { level });
+        let data = &mut self.data;
+        match self.style {
+            Style::Off => {}
+            Style::Columns => {
+                for row in data.iter_mut() {
+                    row[band] = row[band].max(level);
+                }
+                if let Some(pulse) = pulse {
+                    data[4] = [pulse; 5];
+                }
+            }
+            Style::Rows => {
+                data[4 - band] = [level; 5];
+                if let Some(pulse) = pulse {
+                    for row in data.iter_mut() {
+                        row[0] = row[0].max(pulse);
+                    }
+                }
+            }
+            Style::Pulse => {
+                // 拍上点亮中心 3x3，小节第一拍点亮整个点阵，其余音符只亮中心
+                let radius = match beat {
+                    Some(true) => 2,
+                    Some(false) => 1,
+                    None => 0,
+                };
+                for (r, row) in data.iter_mut().enumerate() {
+                    for (c, pixel) in row.iter_mut().enumerate() {
+                        if r.abs_diff(2) <= radius && c.abs_diff(2) <= radius {
+                            *pixel = (*pixel).max(level);
+                        }
+                    }
+                }
+            }
+            Style::Roll => {
+                data[4 - band][4] = level;
+                if let Some(pulse) = pulse {
+                    data[0][4] = data[0][4].max(pulse / 2);
+                }
+            }
+        }
+    }
+
+    pub fn note_off(&mut self) {
+        self.held = None;
+    }
+
+    /// 前进一帧：整体变暗，卷帘样式左移一列并画出正在发声的音
+    pub fn step(&mut self) {
+        if self.held.is_none() {
+            self.idle_frames = self.idle_frames.saturating_add(1);
+        }
+        if self.style == Style::Roll {
+            for row in self.data.iter_mut() {
+                row.copy_within(1.., 0);
+                row[4] = 0;
+            }
+            if let Some((band, level)) = self.held {
+                self.data[4 - band][4] = level;
+            }
+            return;
+        }
+        for pixel in self.data.iter_mut().flatten() {
+            *pixel = pixel.saturating_sub(1);
+        }
+    }
+
+    pub fn frame(&self) -> [[u8; 5]; 5] {
+        self.data
+    }
+}
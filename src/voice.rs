@@ -0,0 +1,62 @@
+use crate::effect::Effect;
+use crate::instrument::Envelope;
+use crate::wavetable::Wave;
+
+pub use musicbox_core::pitch::FREQ_SCALE;
+
+/// 一个正在发声的音符：音高效果叠加音量包络，时间从音符开始计算
+pub struct Voice {
+    freq: u32,
+    from_freq: u32,
+    effect: Effect,
+    envelope: Envelope,
+    wave: Wave,
+    /// 松开的时间及当时的电平
+    released: Option<(u32, u32)>,
+}
+
+impl Voice {
+    /// `freq`、`from_freq` 单位为 Hz，`from_freq` 是滑音的起点（上一个音符）
+    pub fn new(freq: u32, from_freq: u32, envelope: Envelope, wave: Wave, effect: Effect) -> Self {
+        Self {
+            freq: freq * FREQ_SCALE,
+            from_freq: from_freq * FREQ_SCALE,
+            effect,
+            envelope,
+            wave,
+            released: None,
+        }
+    }
+
+    pub fn wave(&self) -> Wave {
+        self.wave
+    }
+
+    pub fn release(&mut self, ms: u32) {
+        if self.released.is_none() {
+            self.released = Some((ms, self.level_at(ms)));
+        }
+    }
+
+    /// 第 `ms` 毫秒的频率，单位 1/`FREQ_SCALE` Hz
+    pub fn freq_at(&self, ms: u32) -> u32 {
+        self.effect.freq_at(self.freq, self.from_freq, ms)
+    }
+
+    /// 第 `ms` 毫秒的包络电平（0-100）
+    pub fn level_at(&self, ms: u32) -> u32 {
+        match self.released {
+            Some((at_ms, from)) if ms >= at_ms => self.envelope.release_level(from, ms - at_ms),
+            _ => self.envelope.level(ms),
+        }
+    }
+
+    /// 第 `ms` 毫秒之后输出是否保持不变，不变时无需继续渲染
+    pub fn settled_at(&self, ms: u32) -> bool {
+        let level_settled = match self.released {
+            Some((at_ms, _)) => ms >= at_ms + self.envelope.release_ms as u32,
+            None => ms >= self.envelope.onset_ms(),
+        };
+        level_settled && (self.effect.settled_at(ms) || self.level_at(ms) == 0)
+    }
+}